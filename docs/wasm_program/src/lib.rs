@@ -11,7 +11,10 @@ pub struct Ret {
 #[wasm_bindgen]
 pub fn vis(_input: String, _output: String) -> Ret {
     let input = util::parse_input(&_input);
-    let output = util::parse_output(&_output);
+    let output = match util::parse_output(&_output) {
+        Ok(output) => output,
+        Err(err) => return Ret { err, vis: String::new() },
+    };
     let (err, vis) = util::vis(&input, &output);
     Ret {
         err: err.to_string(),
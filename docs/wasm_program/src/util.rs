@@ -1,6 +1,8 @@
 #![allow(non_snake_case)]
 use itertools::Itertools;
 use proconio::{input, marker::Chars};
+use torus::geometry::Geometry;
+use torus::moves::{parse_moves, Move};
 use std::{collections::HashSet, ops::RangeBounds};
 use svg::node::{
     element::{Circle, Group, Line, Rectangle, Style, Title},
@@ -27,12 +29,12 @@ pub fn parse_input(f: &str) -> Input {
 }
 
 pub struct Output {
-    pub moves: Vec<char>,
+    pub moves: Vec<Move>,
 }
 
-pub fn parse_output(f: &str) -> Output {
-    let moves = f.chars().collect();
-    Output { moves }
+pub fn parse_output(f: &str) -> Result<Output, String> {
+    let moves = parse_moves(f).map_err(|e| e.to_string())?;
+    Ok(Output { moves })
 }
 
 pub struct State {
@@ -90,119 +92,13 @@ impl State {
         }
         (wrapped_i as usize, wrapped_j as usize)
     }
-    pub fn apply(&mut self, m: char) {
-        match m {
-            '1' => {
-                let (a_i, a_j) = self.blank_tile;
-                let (b_i, b_j) = self.wrap_coordinates(a_i as i32 - 1, a_j as i32);
-                let (c_i, c_j) = self.wrap_coordinates(a_i as i32, a_j as i32 + 1);
-                (self.board[a_i][a_j], self.board[b_i][b_j], self.board[c_i][c_j]) = (self.board[c_i][c_j], self.board[a_i][a_j], self.board[b_i][b_j]);
-                self.blank_tile = (b_i, b_j);
-            }
-            '2' => {
-                let (a_i, a_j) = self.blank_tile;
-                let (b_i, b_j) = self.wrap_coordinates(a_i as i32, a_j as i32 + 1);
-                let (c_i, c_j) = self.wrap_coordinates(a_i as i32 + 1, a_j as i32 + 1);
-                (self.board[a_i][a_j], self.board[b_i][b_j], self.board[c_i][c_j]) = (self.board[c_i][c_j], self.board[a_i][a_j], self.board[b_i][b_j]);
-                self.blank_tile = (b_i, b_j);
-            }
-            '3' => {
-                let (a_i, a_j) = self.blank_tile;
-                let (b_i, b_j) = self.wrap_coordinates(a_i as i32 + 1, a_j as i32 + 1);
-                let (c_i, c_j) = self.wrap_coordinates(a_i as i32 + 1, a_j as i32);
-                (self.board[a_i][a_j], self.board[b_i][b_j], self.board[c_i][c_j]) = (self.board[c_i][c_j], self.board[a_i][a_j], self.board[b_i][b_j]);
-                self.blank_tile = (b_i, b_j);
-            }
-            '4' => {
-                let (a_i, a_j) = self.blank_tile;
-                let (b_i, b_j) = self.wrap_coordinates(a_i as i32 + 1, a_j as i32);
-                let (c_i, c_j) = self.wrap_coordinates(a_i as i32, a_j as i32 - 1);
-                (self.board[a_i][a_j], self.board[b_i][b_j], self.board[c_i][c_j]) = (self.board[c_i][c_j], self.board[a_i][a_j], self.board[b_i][b_j]);
-                self.blank_tile = (b_i, b_j);
-            }
-            '5' => {
-                let (a_i, a_j) = self.blank_tile;
-                let (b_i, b_j) = self.wrap_coordinates(a_i as i32, a_j as i32 - 1);
-                let (c_i, c_j) = self.wrap_coordinates(a_i as i32 - 1, a_j as i32 - 1);
-                (self.board[a_i][a_j], self.board[b_i][b_j], self.board[c_i][c_j]) = (self.board[c_i][c_j], self.board[a_i][a_j], self.board[b_i][b_j]);
-                self.blank_tile = (b_i, b_j);
-            }
-            '6' => {
-                let (a_i, a_j) = self.blank_tile;
-                let (b_i, b_j) = self.wrap_coordinates(a_i as i32 - 1, a_j as i32 - 1);
-                let (c_i, c_j) = self.wrap_coordinates(a_i as i32 - 1, a_j as i32);
-                (self.board[a_i][a_j], self.board[b_i][b_j], self.board[c_i][c_j]) = (self.board[c_i][c_j], self.board[a_i][a_j], self.board[b_i][b_j]);
-                self.blank_tile = (b_i, b_j);
-            }
-            'A' => {
-                let (a_i, a_j) = self.blank_tile;
-                let (b_i, b_j) = self.wrap_coordinates(a_i as i32, a_j as i32 + 1);
-                let (c_i, c_j) = self.wrap_coordinates(a_i as i32 - 1, a_j as i32);
-                (self.board[a_i][a_j], self.board[b_i][b_j], self.board[c_i][c_j]) = (self.board[c_i][c_j], self.board[a_i][a_j], self.board[b_i][b_j]);
-                self.blank_tile = (b_i, b_j);
-            }
-            'B' => {
-                let (a_i, a_j) = self.blank_tile;
-                let (b_i, b_j) = self.wrap_coordinates(a_i as i32 + 1, a_j as i32 + 1);
-                let (c_i, c_j) = self.wrap_coordinates(a_i as i32, a_j as i32 + 1);
-                (self.board[a_i][a_j], self.board[b_i][b_j], self.board[c_i][c_j]) = (self.board[c_i][c_j], self.board[a_i][a_j], self.board[b_i][b_j]);
-                self.blank_tile = (b_i, b_j);
-            }
-            'C' => {
-                let (a_i, a_j) = self.blank_tile;
-                let (b_i, b_j) = self.wrap_coordinates(a_i as i32 + 1, a_j as i32);
-                let (c_i, c_j) = self.wrap_coordinates(a_i as i32 + 1, a_j as i32 + 1);
-                (self.board[a_i][a_j], self.board[b_i][b_j], self.board[c_i][c_j]) = (self.board[c_i][c_j], self.board[a_i][a_j], self.board[b_i][b_j]);
-                self.blank_tile = (b_i, b_j);
-            }
-            'D' => {
-                let (a_i, a_j) = self.blank_tile;
-                let (b_i, b_j) = self.wrap_coordinates(a_i as i32, a_j as i32 - 1);
-                let (c_i, c_j) = self.wrap_coordinates(a_i as i32 + 1, a_j as i32);
-                (self.board[a_i][a_j], self.board[b_i][b_j], self.board[c_i][c_j]) = (self.board[c_i][c_j], self.board[a_i][a_j], self.board[b_i][b_j]);
-                self.blank_tile = (b_i, b_j);
-            }
-            'E' => {
-                let (a_i, a_j) = self.blank_tile;
-                let (b_i, b_j) = self.wrap_coordinates(a_i as i32 - 1, a_j as i32 - 1);
-                let (c_i, c_j) = self.wrap_coordinates(a_i as i32, a_j as i32 - 1);
-                (self.board[a_i][a_j], self.board[b_i][b_j], self.board[c_i][c_j]) = (self.board[c_i][c_j], self.board[a_i][a_j], self.board[b_i][b_j]);
-                self.blank_tile = (b_i, b_j);
-            }
-            'F' => {
-                let (a_i, a_j) = self.blank_tile;
-                let (b_i, b_j) = self.wrap_coordinates(a_i as i32 - 1, a_j as i32);
-                let (c_i, c_j) = self.wrap_coordinates(a_i as i32 - 1, a_j as i32 - 1);
-                (self.board[a_i][a_j], self.board[b_i][b_j], self.board[c_i][c_j]) = (self.board[c_i][c_j], self.board[a_i][a_j], self.board[b_i][b_j]);
-                self.blank_tile = (b_i, b_j);
-            }
-            _ => {}
-        }
-    }
-    pub fn raw_distance(&self, now_i: i32, now_j: i32, target_i: i32, target_j: i32) -> i32 {
-        let offsets: [(i32, i32); 7] = [
-            (0, 0),
-            (-(self.n as i32) + 1, self.n as i32),
-            (self.n as i32, 2 * self.n as i32 - 1),
-            (2 * self.n as i32 - 1, self.n as i32 - 1),
-            (self.n as i32 - 1, -(self.n as i32)),
-            (-(self.n as i32), -2 * self.n as i32 + 1),
-            (-2 * self.n as i32 + 1, -(self.n as i32) + 1),
-        ];
-        let mut min_distance = std::i32::MAX;
-        for &(di, dj) in offsets.iter() {
-            let wrapped_i = now_i as i32 + di;
-            let wrapped_j = now_j as i32 + dj;
-            let distance = if wrapped_i < target_i && wrapped_j < target_j {
-                (target_i - wrapped_i).max(target_j - wrapped_j)
-            } else if wrapped_i > target_i && wrapped_j > target_j {
-                (wrapped_i - target_i).max(wrapped_j - target_j)
-            } else {
-                (wrapped_i - target_i).unsigned_abs() as i32 + (wrapped_j - target_j).unsigned_abs() as i32
-            };
-            min_distance = min_distance.min(distance);
-        }
-        min_distance
+    pub fn apply(&mut self, m: Move) {
+        let (a_i, a_j) = self.blank_tile;
+        let ((b_di, b_dj), (c_di, c_dj)) = m.neighbors();
+        let (b_i, b_j) = self.wrap_coordinates(a_i as i32 + b_di, a_j as i32 + b_dj);
+        let (c_i, c_j) = self.wrap_coordinates(a_i as i32 + c_di, a_j as i32 + c_dj);
+        (self.board[a_i][a_j], self.board[b_i][b_j], self.board[c_i][c_j]) = (self.board[c_i][c_j], self.board[a_i][a_j], self.board[b_i][b_j]);
+        self.blank_tile = (b_i, b_j);
     }
 }
 
@@ -220,6 +116,8 @@ pub fn vis(input: &Input, output: &Output) -> (String, String) {
         }
     }
 
+    let geometry = Geometry::get(state.n);
+
     let mut err = String::new();
     let D = 600.0 / (state.h + 2) as f64;
     let W = 600.0;
@@ -253,7 +151,7 @@ pub fn vis(input: &Input, output: &Output) -> (String, String) {
                             .set("y", (i + 1) as f64 * D)
                             .set("width", D)
                             .set("height", D)
-                            .set("fill", color(state.raw_distance(i, j, target_i, target_j) as f64 / (state.n) as f64))
+                            .set("fill", color(geometry.distance((i as usize, j as usize), (target_i as usize, target_j as usize)) as f64 / (state.n) as f64))
                             .set("stroke", "black")
                             .set("stroke-width", 1),
                     );
@@ -2,171 +2,309 @@
 // https://github.com/rhoo19937/beam-search-library/blob/main/src/tree_beam.rs
 // Thank you to the author for their excellent work!
 
-use crate::input::Input;
+use crate::budget::TimeBudget;
+use crate::endgame::{self, Perimeter};
+use crate::evaluator::{Evaluator, Weights};
+use crate::moves::Move;
+use crate::schedule::PhaseKey;
 use crate::state::State;
+use crate::utils::mix;
+use crate::SolverConfig;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// Index type of the node pool. `u32` halves the memory of a node, `u64` allows huge pools.
+pub trait NodeIndex: Copy + Eq + Send + Sync {
+    const NONE: Self;
+    /// Nodes the pool can index.
+    const CAPACITY: usize;
+    fn from_usize(x: usize) -> Self;
+    fn to_usize(self) -> usize;
+}
 
-#[allow(non_camel_case_types)]
-type uint = u32;
+impl NodeIndex for u32 {
+    const NONE: Self = !0;
+    const CAPACITY: usize = Self::NONE as usize;
+    fn from_usize(x: usize) -> Self {
+        assert!(x < Self::NONE as usize, "node pool exceeds the u32 index range");
+        x as u32
+    }
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+}
+
+impl NodeIndex for u64 {
+    const NONE: Self = !0;
+    const CAPACITY: usize = usize::MAX;
+    fn from_usize(x: usize) -> Self {
+        x as u64
+    }
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+}
 
 #[derive(Clone)]
-struct Cand {
-    op: char,
-    parent: uint,
+struct Cand<I> {
+    op: Move,
+    parent: I,
     score: i64,
-    empty_i: usize,
-    empty_j: usize,
+    /// see `DiversityPolicy::key`
+    key: u32,
     hash: u64,
+    solved: bool,
+    /// moves left according to `SolverConfig::endgame_table`, if the table holds the state
+    finish: Option<u32>,
+    /// `State::fingerprint`, only kept when checking for hash collisions
+    fingerprint: Option<Box<[u16]>>,
 }
 
-impl Cand {
-    fn to_node(&self) -> Node {
+impl<I: NodeIndex> Cand<I> {
+    fn to_node(&self) -> Node<I> {
         Node {
-            child: !0,
-            prev: !0,
-            next: !0,
-            op: self.op,
+            child: I::NONE,
+            prev: I::NONE,
+            next: I::NONE,
+            op: Some(self.op),
             parent: self.parent,
             score: self.score,
         }
     }
 }
 
-#[derive(Clone, Default)]
-struct Node {
-    op: char,
-    parent: uint,
-    child: uint,
-    prev: uint,
-    next: uint,
+#[derive(Clone)]
+struct Node<I> {
+    /// `None` only at the root
+    op: Option<Move>,
+    parent: I,
+    child: I,
+    prev: I,
+    next: I,
     score: i64,
 }
 
-const MAX_WIDTH: usize = 3000;
-const MAX_NODES: usize = MAX_WIDTH * 50;
+pub struct SearchResult {
+    pub moves: Vec<Move>,
+    /// score of the state reached by `moves`
+    pub score: i64,
+    pub solved: bool,
+    /// the pool ran out of indices of its type and the search stopped, see `NodeIndex`
+    pub pool_full: bool,
+}
+
+/// Share of the time left the endgame may take, building the perimeter and meeting it, when the
+/// search has a time limit.
+const ENDGAME_SHARE: f64 = 0.25;
 
-pub struct BeamSearch {
+pub struct BeamSearch<I: NodeIndex = u32> {
     state: State,
-    leaf: Vec<uint>,
-    next_leaf: Vec<uint>,
-    nodes: Vec<Node>,
+    leaf: Vec<I>,
+    next_leaf: Vec<I>,
+    nodes: Vec<Node<I>>,
     cur_node: usize,
-    free: Vec<uint>,
+    free: Vec<I>,
+    peak_nodes: usize,
+    /// a node was dropped because the pool had no index left for it
+    pool_full: bool,
+    evaluator: Box<dyn Evaluator>,
 }
 
-impl BeamSearch {
-    pub fn new(state: State, prev_op: char) -> BeamSearch {
-        assert!(MAX_NODES < uint::MAX as usize, "uintのサイズが足りないよ");
-
-        let mut score = 0;
-        for i in 0..state.tile_positions.len() {
-            score += state.weighted_distance(i);
-        }
-        score += state.mismatch_cost();
+impl<I: NodeIndex> BeamSearch<I> {
+    /// `capacity` is only the initial size of the node pool; it grows on demand, up to
+    /// `I::CAPACITY` nodes.
+    pub fn new(state: State, prev_op: Option<Move>, width: usize, capacity: usize, evaluator: Box<dyn Evaluator>) -> BeamSearch<I> {
+        let score = evaluator.evaluate(&state);
 
         let node = Node {
             op: prev_op,
-            parent: !0,
-            child: !0,
-            prev: !0,
-            next: !0,
-            score: score as i64,
+            parent: I::NONE,
+            child: I::NONE,
+            prev: I::NONE,
+            next: I::NONE,
+            score,
         };
-        let mut nodes = vec![Node::default(); MAX_NODES];
-        nodes[0] = node;
+        let mut nodes = Vec::with_capacity(capacity.max(1));
+        nodes.push(node);
 
-        let mut leaf = Vec::with_capacity(MAX_WIDTH);
-        leaf.push(0);
-        let next_leaf = Vec::with_capacity(MAX_WIDTH);
-        let free = (1..nodes.len() as uint).rev().collect();
+        let mut leaf = Vec::with_capacity(width);
+        leaf.push(I::from_usize(0));
+        let next_leaf = Vec::with_capacity(width);
 
-        BeamSearch { state, nodes, free, leaf, next_leaf, cur_node: 0 }
+        BeamSearch { state, nodes, free: vec![], leaf, next_leaf, cur_node: 0, peak_nodes: 1, pool_full: false, evaluator }
     }
 
-    fn add_node(&mut self, cand: Cand) {
-        let next = self.nodes[cand.parent as usize].child;
+    /// The evaluator, for another search from the same state.
+    pub fn into_evaluator(self) -> Box<dyn Evaluator> {
+        self.evaluator
+    }
+
+    /// Largest number of nodes alive at the same time so far.
+    pub fn peak_nodes(&self) -> usize {
+        self.peak_nodes
+    }
+
+    fn add_node(&mut self, cand: Cand<I>) {
+        if self.free.is_empty() && self.nodes.len() >= I::CAPACITY {
+            self.pool_full = true;
+            return;
+        }
+        let next = self.nodes[cand.parent.to_usize()].child;
 
         let new = if let Some(n) = self.free.pop() {
-            self.nodes[n as usize] = Node { next, ..cand.to_node() };
+            self.nodes[n.to_usize()] = Node { next, ..cand.to_node() };
             n
         } else {
-            let n = self.nodes.len() as uint;
-            assert!(n != 0, "uintのサイズが足りないよ");
+            let n = I::from_usize(self.nodes.len());
             self.nodes.push(Node { next, ..cand.to_node() });
             n
         };
 
-        if next != !0 {
-            self.nodes[next as usize].prev = new;
+        if next != I::NONE {
+            self.nodes[next.to_usize()].prev = new;
         }
-        self.nodes[cand.parent as usize].child = new;
+        self.nodes[cand.parent.to_usize()].child = new;
 
         self.next_leaf.push(new);
+        self.peak_nodes = self.peak_nodes.max(self.nodes.len() - self.free.len());
     }
 
-    fn del_node(&mut self, mut idx: uint) {
+    fn del_node(&mut self, mut idx: I) {
         loop {
             self.free.push(idx);
-            let Node { prev, next, parent, .. } = self.nodes[idx as usize];
-            assert_ne!(parent, !0, "全てのノードを消そうとしています");
+            let Node { prev, next, parent, .. } = self.nodes[idx.to_usize()];
+            assert!(parent != I::NONE, "全てのノードを消そうとしています");
 
-            if prev & next == !0 {
+            if prev == I::NONE && next == I::NONE {
                 idx = parent;
                 continue;
             }
 
-            if prev != !0 {
-                self.nodes[prev as usize].next = next;
+            if prev != I::NONE {
+                self.nodes[prev.to_usize()].next = next;
             } else {
-                self.nodes[parent as usize].child = next;
+                self.nodes[parent.to_usize()].child = next;
             }
-            if next != !0 {
-                self.nodes[next as usize].prev = prev;
+            if next != I::NONE {
+                self.nodes[next.to_usize()].prev = prev;
             }
 
             break;
         }
     }
 
-    fn no_dfs(&mut self, cands: &mut Vec<Cand>) {
+    fn no_dfs(&mut self, cands: &mut Vec<Cand<I>>, config: &SolverConfig) {
+        // skip the path shared by every leaf
         loop {
-            let Node { next, child, .. } = self.nodes[self.cur_node];
-            if next == !0 || child == !0 {
+            let child = self.nodes[self.cur_node].child;
+            if child == I::NONE || self.nodes[child.to_usize()].next != I::NONE {
                 break;
             }
-            self.cur_node = child as usize;
-            self.state.apply(self.nodes[self.cur_node].op);
+            self.cur_node = child.to_usize();
+            self.state.apply(self.nodes[self.cur_node].op.unwrap());
         }
 
+        // a narrow layer does not pay for spawning the workers
+        if config.threads <= 1 || self.leaf.len() < 2 * config.threads {
+            dfs(&self.nodes, &mut self.state, &*self.evaluator, config, self.cur_node, cands);
+        } else {
+            self.parallel_dfs(cands, config);
+        }
+    }
+
+    /// Splits the tree below `cur_node` into subtrees handed out to `threads` workers, each
+    /// replaying its own copy of the state. Candidates are gathered in DFS order, so the result
+    /// does not depend on the number of threads.
+    fn parallel_dfs(&mut self, cands: &mut Vec<Cand<I>>, config: &SolverConfig) {
+        let threads = config.threads;
         let root = self.cur_node;
-        loop {
-            let child = self.nodes[self.cur_node].child;
-            if child == !0 {
-                self.append_cands(self.cur_node, cands);
-                loop {
-                    if self.cur_node == root {
-                        return;
-                    }
-                    let node = &self.nodes[self.cur_node];
-                    self.state.revert(node.op);
-                    if node.next != !0 {
-                        self.cur_node = node.next as usize;
-                        self.state.apply(self.nodes[self.cur_node].op);
-                        break;
-                    }
-                    self.cur_node = node.parent as usize;
+        let mut frontier = vec![root];
+        while frontier.len() < threads * 8 {
+            let mut next_frontier = Vec::with_capacity(frontier.len() * 2);
+            let mut expanded = false;
+            for &idx in frontier.iter() {
+                let mut child = self.nodes[idx].child;
+                if child == I::NONE {
+                    next_frontier.push(idx);
+                    continue;
                 }
-            } else {
-                self.cur_node = child as usize;
-                self.state.apply(self.nodes[self.cur_node].op);
+                expanded = true;
+                while child != I::NONE {
+                    next_frontier.push(child.to_usize());
+                    child = self.nodes[child.to_usize()].next;
+                }
+            }
+            frontier = next_frontier;
+            if !expanded {
+                break;
             }
         }
+
+        let nodes = &self.nodes;
+        let state = &self.state;
+        let evaluator = &*self.evaluator;
+        let frontier = &frontier;
+        let counter = AtomicUsize::new(0);
+        let mut results: Vec<Vec<Cand<I>>> = (0..frontier.len()).map(|_| vec![]).collect();
+        std::thread::scope(|scope| {
+            let handles = (0..threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut state = state.clone();
+                        let mut done = vec![];
+                        loop {
+                            let k = counter.fetch_add(1, Ordering::Relaxed);
+                            if k >= frontier.len() {
+                                break;
+                            }
+                            let mut path = vec![];
+                            let mut idx = frontier[k];
+                            while idx != root {
+                                path.push(nodes[idx].op.unwrap());
+                                idx = nodes[idx].parent.to_usize();
+                            }
+                            for &op in path.iter().rev() {
+                                state.apply(op);
+                            }
+                            let mut subtree_cands = vec![];
+                            dfs(nodes, &mut state, evaluator, config, frontier[k], &mut subtree_cands);
+                            for &op in path.iter() {
+                                state.revert(op);
+                            }
+                            done.push((k, subtree_cands));
+                        }
+                        done
+                    })
+                })
+                .collect::<Vec<_>>();
+            for handle in handles {
+                for (k, subtree_cands) in handle.join().unwrap() {
+                    results[k] = subtree_cands;
+                }
+            }
+        });
+        for subtree_cands in results {
+            cands.extend(subtree_cands);
+        }
     }
 
-    fn enum_cands(&mut self, cands: &mut Vec<Cand>) {
-        self.no_dfs(cands);
+    /// Switches the evaluator to `weights` and recomputes the score of every leaf with them.
+    fn set_weights(&mut self, weights: &Weights) {
+        self.state.set_distance_exponent(weights.distance_exponent);
+        self.evaluator.set_weights(weights);
+        let mut scores = vec![];
+        let evaluator = &*self.evaluator;
+        for_each_leaf(&self.nodes, &mut self.state, self.cur_node, |state, idx| scores.push((idx, evaluator.evaluate(state))));
+        for (idx, score) in scores {
+            self.nodes[idx].score = score;
+        }
     }
 
-    fn update(&mut self, cands: impl Iterator<Item = Cand>) {
+    fn enum_cands(&mut self, cands: &mut Vec<Cand<I>>, config: &SolverConfig) {
+        self.no_dfs(cands, config);
+    }
+
+    fn update(&mut self, cands: impl Iterator<Item = Cand<I>>) {
         self.next_leaf.clear();
         for cand in cands {
             self.add_node(cand);
@@ -174,7 +312,7 @@ impl BeamSearch {
 
         for i in 0..self.leaf.len() {
             let n = self.leaf[i];
-            if self.nodes[n as usize].child == !0 {
+            if self.nodes[n.to_usize()].child == I::NONE {
                 self.del_node(n);
             }
         }
@@ -182,14 +320,14 @@ impl BeamSearch {
         std::mem::swap(&mut self.leaf, &mut self.next_leaf);
     }
 
-    fn restore(&self, mut idx: uint) -> Vec<char> {
+    fn restore(&self, mut idx: I) -> Vec<Move> {
         let mut ret = vec![];
         loop {
-            let Node { op, parent, .. } = self.nodes[idx as usize];
-            if parent == !0 {
+            let Node { op, parent, .. } = self.nodes[idx.to_usize()];
+            if parent == I::NONE {
                 break;
             }
-            ret.push(op);
+            ret.push(op.unwrap());
             idx = parent;
         }
 
@@ -197,100 +335,304 @@ impl BeamSearch {
         ret
     }
 
-    fn append_cands(&mut self, idx: usize, cands: &mut Vec<Cand>) {
-        let node = &self.nodes[idx];
-        assert_eq!(node.child, !0);
+    fn restore_cand(&self, cand: &Cand<I>) -> Vec<Move> {
+        let mut ret = self.restore(cand.parent);
+        ret.push(cand.op);
+        ret
+    }
 
-        let was_clockwise = ['1', '2', '3', '4', '5', '6'].contains(&node.op);
-        let was_anticlockwise = ['A', 'B', 'C', 'D', 'E', 'F'].contains(&node.op);
-        for op in "123456ABCDEF".chars() {
-            if was_clockwise && ['1', '2', '3', '4', '5', '6'].contains(&op) {
-                continue;
-            }
-            if was_anticlockwise && ['A', 'B', 'C', 'D', 'E', 'F'].contains(&op) {
-                continue;
-            }
-            let surrounding = self.state.surrounding(0);
-            let mut diff = 0;
-            for &i in surrounding.iter() {
-                diff -= self.state.weighted_distance(i) as i64;
-            }
-            diff -= self.state.mismatch_cost() as i64;
-            self.state.apply(op);
-            let next_hash = self.state.hash;
-            for &i in surrounding.iter() {
-                diff += self.state.weighted_distance(i) as i64;
+    /// State reached by `cand`; `self.state` must be that of `cur_node`.
+    fn state_of(&self, cand: &Cand<I>) -> State {
+        let mut ops = vec![cand.op];
+        let mut idx = cand.parent.to_usize();
+        while idx != self.cur_node {
+            let node = &self.nodes[idx];
+            ops.push(node.op.unwrap());
+            idx = node.parent.to_usize();
+        }
+        let mut state = self.state.clone();
+        for &op in ops.iter().rev() {
+            state.apply(op);
+        }
+        state
+    }
+
+    /// `endgame::meet` from the best `endgame_width` distinct candidates, against `endgame_table` or
+    /// the shared perimeter of the board, building the perimeter and meeting it before `deadline`.
+    /// Skipped if the build leaves no time to meet.
+    fn endgame(&self, cands: &[Cand<I>], config: &SolverConfig, deadline: Option<Instant>) -> Option<(Cand<I>, Vec<Move>)> {
+        let mut best: Vec<&Cand<I>> = cands.iter().collect();
+        best.sort_unstable_by_key(|a| (a.score, tie_break(a.hash, config.seed)));
+        let mut seen = rustc_hash::FxHashSet::default();
+        best.retain(|cand| seen.insert(cand.hash));
+        best.truncate(config.endgame_width.max(1));
+        let perimeter = match &config.endgame_table {
+            Some(table) => table.clone(),
+            None => Perimeter::get(self.state.n(), config.hash, deadline),
+        };
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            if config.verbose >= 1 {
+                eprintln!("endgame: no time left after {} states within {} of the goal, skipped", perimeter.len(), perimeter.depth());
             }
-            diff += self.state.mismatch_cost() as i64;
-            let (empty_i, empty_j) = self.state.zero_position;
-            self.state.revert(op);
-
-            let cand = Cand {
-                op,
-                parent: idx as uint,
-                score: node.score + diff,
-                empty_i,
-                empty_j,
-                hash: next_hash,
-            };
-            cands.push(cand);
+            return None;
         }
+        let mut starts: Vec<_> = best.iter().map(|cand| (self.state_of(cand), cand.op)).collect();
+        let (k, tail) = endgame::meet(&perimeter, &mut starts, config, deadline)?;
+        Some((best[k].clone(), tail))
     }
 
-    pub fn solve(&mut self, input: &Input) -> Vec<char> {
-        let M = MAX_WIDTH;
+    /// Runs until a candidate is solved (see `State::is_solved`), or until `max_depth` or `stall_limit` stops the
+    /// search, in which case the best candidate of the last depth is returned unsolved. With `endgame`, the
+    /// first time the best score drops below it, the best candidates try `endgame::meet` and the search goes on
+    /// if they do not meet. With `endgame_table`, a candidate found in the table counts as solved, followed by
+    /// its tail from the table.
+    pub fn solve(&mut self, config: &SolverConfig) -> SearchResult {
+        // a table of another board or hash would give wrong distances
+        let without_table;
+        let config = match &config.endgame_table {
+            Some(table) if !table.fits(self.state.n(), config.hash) => {
+                if config.verbose >= 1 {
+                    eprintln!("endgame table: built for n = {}, {:?}, ignored", table.n(), table.hash());
+                }
+                without_table = SolverConfig { endgame_table: None, ..config.clone() };
+                &without_table
+            }
+            _ => config,
+        };
+        let cells = self.state.geometry.cells().len();
+        let start = Instant::now();
+        let mut budget = config.time_limit.map(|limit| TimeBudget::new(limit, config.diversity.cap(), config.beam_width));
+        let mut width = config.beam_width;
 
-        let mut cands: Vec<Cand> = vec![];
+        let mut cands: Vec<Cand<I>> = vec![];
         let mut set = rustc_hash::FxHashSet::default();
-
+        // with `check_collisions`: the fingerprint of the first candidate of each hash
+        let mut fingerprints = rustc_hash::FxHashMap::default();
+        let (mut duplicates, mut collisions) = (0, 0);
+
+        let mut best_dist = i64::MAX;
+        let mut last_improved = 0;
+        let mut min_dist = self.nodes[self.cur_node].score;
+        let mut position = f64::NEG_INFINITY;
         let mut t = 0;
-        'outer: loop {
+        let mut handed_off = false;
+        // a candidate in the endgame table or met by the endgame, and the moves from it to the
+        // solved board
+        let mut finish = None;
+        let solved = 'outer: loop {
+            let depth_start = Instant::now();
             if t != 0 {
-                let M0 = (M as f64 * 2.).round() as usize;
-                cands.sort_unstable_by_key(|a| a.score);
-                let mut count = vec![vec![0; 2 * input.n - 1]; 2 * input.n - 1];
+                // saturates to `usize::MAX` when uncapped
+                let m0 = (width as f64 * config.candidate_factor).round() as usize;
+                cands.sort_unstable_by_key(|a| (a.score, tie_break(a.hash, config.seed)));
+                let mut count = vec![0; config.diversity.keys(cells)];
                 let mut new_cands = vec![];
-                let width = 10;
                 for cand in cands.into_iter() {
-                    if count[cand.empty_i][cand.empty_j] == width {
+                    if new_cands.len() == m0 {
+                        break;
+                    }
+                    if count[cand.key as usize] == config.diversity.cap() {
                         continue;
                     }
-                    count[cand.empty_i][cand.empty_j] += 1;
+                    count[cand.key as usize] += 1;
                     new_cands.push(cand);
                 }
                 cands = new_cands;
                 set.clear();
-
-                self.update(cands.drain(..).filter(|cand| set.insert(cand.hash)).take(M));
+                fingerprints.clear();
+
+                let unique = cands.drain(..).filter(|cand| {
+                    let Some(fingerprint) = &cand.fingerprint else {
+                        return set.insert(cand.hash);
+                    };
+                    match fingerprints.get(&cand.hash) {
+                        None => {
+                            set.insert(cand.hash);
+                            fingerprints.insert(cand.hash, fingerprint.clone());
+                            true
+                        }
+                        Some(first) => {
+                            duplicates += 1;
+                            collisions += (first != fingerprint) as usize;
+                            false
+                        }
+                    }
+                });
+                self.update(unique.take(width));
+                if self.pool_full {
+                    break false;
+                }
+            }
+            if let Some(schedule) = &config.schedule {
+                // phases only move forward, even if a new weighting raises the score again
+                let value = match schedule.key {
+                    PhaseKey::Depth => t as f64,
+                    PhaseKey::Score => min_dist as f64,
+                };
+                let next_position = schedule.position(value).max(position);
+                if next_position != position {
+                    position = next_position;
+                    let weights = schedule.weights(position);
+                    self.set_weights(&weights);
+                    // scores under different weights do not compare
+                    best_dist = i64::MAX;
+                    if config.verbose >= 1 {
+                        eprintln!("t: {}, phase {:.3}: {:?}", t, position, weights);
+                    }
+                }
             }
             t += 1;
 
             cands.clear();
-            self.enum_cands(&mut cands);
-            assert!(!cands.is_empty());
-            let mut min_dist = 1 << 30;
+            self.enum_cands(&mut cands, config);
+            if cands.is_empty() {
+                break false;
+            }
+            min_dist = 1 << 30;
             for cand in cands.iter() {
-                let score = cand.score;
-                if score == 0 {
-                    break 'outer;
+                if cand.solved {
+                    break 'outer true;
+                }
+                min_dist = min_dist.min(cand.score);
+            }
+            if let Some(table) = &config.endgame_table {
+                if let Some(cand) = cands.iter().filter(|cand| cand.finish.is_some()).min_by_key(|cand| cand.finish) {
+                    if let Some(tail) = table.path_to_goal(&self.state_of(cand), cand.op) {
+                        if config.verbose >= 1 {
+                            eprintln!("t: {}, in the endgame table, {} moves left", t, tail.len());
+                        }
+                        finish = Some((cand.clone(), tail));
+                        break 'outer true;
+                    }
+                }
+            }
+            if !handed_off && config.endgame.is_some_and(|threshold| min_dist < threshold) {
+                handed_off = true;
+                let deadline = budget.as_ref().map(|budget| budget.deadline(ENDGAME_SHARE));
+                if let Some((cand, tail)) = self.endgame(&cands, config, deadline) {
+                    finish = Some((cand, tail));
+                    break 'outer true;
                 }
-                min_dist = min_dist.min(score);
             }
-            if t % 10 == 0 {
-                eprintln!("t: {}, dist: {}", t, min_dist);
+            if min_dist < best_dist {
+                best_dist = min_dist;
+                last_improved = t;
+            }
+            if let Some(budget) = &mut budget {
+                if budget.expired() {
+                    break false;
+                }
+                width = budget.next_width(t, width, self.leaf.len(), depth_start.elapsed(), min_dist);
+            }
+            if config.verbose >= 2 || (config.verbose == 1 && t % 10 == 0) {
+                eprintln!("t: {}, dist: {}, width: {}, time: {:.2}s", t, min_dist, width, start.elapsed().as_secs_f64());
                 // let best_candidate = cands.iter().min_by_key(|a| a.score).unwrap();
                 // let mut ret = self.restore(best_candidate.parent.clone());
                 // ret.push(best_candidate.op.clone());
                 // let ret_str = ret.iter().map(|op| op.to_string()).collect::<String>();
                 // eprintln!("Current best: {}", ret_str);
             }
+            if config.max_depth.is_some_and(|max_depth| t >= max_depth) {
+                break false;
+            }
+            if config.stall_limit.is_some_and(|stall_limit| t - last_improved >= stall_limit) {
+                break false;
+            }
+        };
+
+        if config.check_collisions {
+            eprintln!("hash collisions: {} of {} duplicates dropped", collisions, duplicates);
+        }
+        if config.verbose >= 1 {
+            let status = if solved { "solved" } else { "stopped" };
+            eprintln!("t: {}, {} in {:.2}s, peak nodes: {}", t, status, start.elapsed().as_secs_f64(), self.peak_nodes);
         }
 
-        let best = cands.into_iter().min_by_key(|a| a.score).unwrap();
+        if let Some((cand, tail)) = finish {
+            let mut moves = self.restore_cand(&cand);
+            moves.extend(tail);
+            return SearchResult { moves, score: 0, solved, pool_full: false };
+        }
 
-        let mut ret = self.restore(best.parent);
-        ret.push(best.op);
+        let Some(best) = cands.into_iter().min_by_key(|a| (!a.solved, a.score)) else {
+            let best = *self.leaf.iter().min_by_key(|&&n| self.nodes[n.to_usize()].score).unwrap();
+            return SearchResult { moves: self.restore(best), score: self.nodes[best.to_usize()].score, solved, pool_full: self.pool_full };
+        };
 
-        ret
+        SearchResult { moves: self.restore_cand(&best), score: best.score, solved, pool_full: self.pool_full }
+    }
+}
+
+/// Sort key among candidates of equal score, see `SolverConfig::seed`.
+fn tie_break(hash: u64, seed: u64) -> u64 {
+    if seed == 0 {
+        0
+    } else {
+        mix(hash ^ seed)
+    }
+}
+
+/// Calls `visit` on every leaf below `root` with `state` set to the state of that leaf, starting
+/// with `state` set to the state of `root`.
+fn for_each_leaf<I: NodeIndex>(nodes: &[Node<I>], state: &mut State, root: usize, mut visit: impl FnMut(&mut State, usize)) {
+    let mut cur_node = root;
+    loop {
+        let child = nodes[cur_node].child;
+        if child == I::NONE {
+            visit(state, cur_node);
+            loop {
+                if cur_node == root {
+                    return;
+                }
+                let node = &nodes[cur_node];
+                state.revert(node.op.unwrap());
+                if node.next != I::NONE {
+                    cur_node = node.next.to_usize();
+                    state.apply(nodes[cur_node].op.unwrap());
+                    break;
+                }
+                cur_node = node.parent.to_usize();
+            }
+        } else {
+            cur_node = child.to_usize();
+            state.apply(nodes[cur_node].op.unwrap());
+        }
+    }
+}
+
+/// Expands every leaf below `root`, with `state` set to the state of `root`.
+fn dfs<I: NodeIndex>(nodes: &[Node<I>], state: &mut State, evaluator: &dyn Evaluator, config: &SolverConfig, root: usize, cands: &mut Vec<Cand<I>>) {
+    for_each_leaf(nodes, state, root, |state, idx| append_cands(nodes, state, evaluator, config, idx, cands));
+}
+
+fn append_cands<I: NodeIndex>(nodes: &[Node<I>], state: &mut State, evaluator: &dyn Evaluator, config: &SolverConfig, idx: usize, cands: &mut Vec<Cand<I>>) {
+    let node = &nodes[idx];
+    assert!(node.child == I::NONE);
+
+    let was_clockwise = node.op.map(Move::is_clockwise);
+    for op in Move::ALL {
+        if was_clockwise == Some(op.is_clockwise()) {
+            continue;
+        }
+        let diff = evaluator.delta(state, op);
+        state.apply(op);
+        let next_hash = state.hash;
+        let key = config.diversity.key(state, op) as u32;
+        let solved = state.is_solved();
+        let finish = config.endgame_table.as_ref().and_then(|table| table.distance(state));
+        let fingerprint = config.check_collisions.then(|| state.fingerprint(op));
+        state.revert(op);
+
+        let cand = Cand {
+            op,
+            parent: I::from_usize(idx),
+            score: node.score + diff,
+            key,
+            hash: next_hash,
+            solved,
+            finish,
+            fingerprint,
+        };
+        cands.push(cand);
     }
 }
@@ -0,0 +1,57 @@
+use clap::Parser;
+use solver::bound::lower_bound;
+use solver::moves::parse_moves;
+use solver::State;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(about = "Reports a lower bound on the solution length of each instance and the gap to the saved solutions")]
+struct Args {
+    /// directory with the in{n}.txt instances
+    #[arg(long, default_value = "input")]
+    input_dir: PathBuf,
+    /// directory with the out{n}.txt solutions
+    #[arg(long, default_value = "output")]
+    output_dir: PathBuf,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let mut inputs = vec![];
+    for entry in std::fs::read_dir(&args.input_dir).expect("failed to read input directory") {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        if let Some(id) = name.strip_prefix("in").and_then(|rest| rest.strip_suffix(".txt")) {
+            let input = solver::input::parse_input(&std::fs::read_to_string(&path).unwrap());
+            inputs.push((id.to_string(), input));
+        }
+    }
+    inputs.sort_by_key(|(_, input)| input.n);
+
+    println!("{:>4} {:>8} {:>8} {:>8} {:>7}", "n", "bound", "length", "gap", "ratio");
+    for (id, input) in inputs.iter() {
+        let mut state = State::from_input(input);
+        let bound = lower_bound(&state);
+        let path = args.output_dir.join(format!("out{}.txt", id));
+        let Ok(text) = std::fs::read_to_string(&path) else {
+            println!("{:>4} {:>8} {:>8}", input.n, bound, "-");
+            continue;
+        };
+        let moves = match parse_moves(&text) {
+            Ok(moves) => moves,
+            Err(e) => {
+                println!("{:>4} {:>8} {}: {}", input.n, bound, path.display(), e);
+                continue;
+            }
+        };
+        let alternating = moves.windows(2).all(|w| w[0].is_clockwise() != w[1].is_clockwise());
+        moves.iter().for_each(|&m| state.apply(m));
+        if !alternating || lower_bound(&state) != 0 {
+            println!("{:>4} {:>8} {:>8} not a solution", input.n, bound, moves.len());
+            continue;
+        }
+        let gap = moves.len() - bound as usize;
+        println!("{:>4} {:>8} {:>8} {:>8} {:>7.3}", input.n, bound, moves.len(), gap, moves.len() as f64 / bound.max(1) as f64);
+    }
+}
@@ -0,0 +1,39 @@
+use clap::Parser;
+use solver::endgame::Perimeter;
+use solver::hash::HashKind;
+use std::path::PathBuf;
+use std::time::Instant;
+
+#[derive(Parser)]
+#[command(about = "Builds an endgame table for the solver's --endgame-table option")]
+struct Args {
+    /// board size
+    #[arg(short)]
+    n: usize,
+    /// moves from the solved board the table reaches; each one multiplies its size by about 5
+    #[arg(short, long, default_value_t = 8)]
+    depth: u32,
+    /// hash of the states, as given to the solver's --hash
+    #[arg(long, default_value = "polynomial")]
+    hash: HashKind,
+    /// output file [default: endgame/n{n}_d{depth}.bin]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+fn main() {
+    let args = Args::parse();
+    assert!(args.n >= 2, "n must be at least 2");
+    assert!(args.depth <= u8::MAX as u32, "depth must be at most 255");
+
+    let start = Instant::now();
+    let table = Perimeter::build_to_depth(args.n, args.hash, args.depth);
+    eprintln!("built n = {}, depth {}: {} states, {:.2}s", args.n, args.depth, table.len(), start.elapsed().as_secs_f64());
+
+    let path = args.output.unwrap_or_else(|| PathBuf::from(format!("endgame/n{}_d{}.bin", args.n, args.depth)));
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).expect("failed to create output directory");
+    }
+    table.save(&path).expect("failed to write endgame table");
+    eprintln!("wrote {}", path.display());
+}
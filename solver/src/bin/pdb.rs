@@ -0,0 +1,45 @@
+use clap::Parser;
+use solver::pattern::PatternDatabase;
+use std::path::PathBuf;
+use std::time::Instant;
+
+#[derive(Parser)]
+#[command(about = "Builds a pattern database for the solver's --pdb option")]
+struct Args {
+    /// board size
+    #[arg(short)]
+    n: usize,
+    /// tiles per group: 1 for single tiles, 2 for horizontally adjacent pairs, 3 for triples
+    #[arg(short, long, default_value_t = 1)]
+    group_size: usize,
+    /// count only the moves of the group's tiles, so that the values of disjoint groups add up
+    #[arg(short, long)]
+    additive: bool,
+    /// output file [default: pdb/n{n}_g{group_size}.bin, or pdb/n{n}_g{group_size}_additive.bin]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+fn main() {
+    let args = Args::parse();
+    assert!(args.n >= 2, "n must be at least 2");
+    assert!((1..=3).contains(&args.group_size), "group size must be 1, 2 or 3");
+
+    if PatternDatabase::table_len(args.n, args.group_size).is_none() {
+        eprintln!("a pattern database of {} tiles is too large for n = {}", args.group_size, args.n);
+        std::process::exit(2);
+    }
+
+    let shape = (0..args.group_size as i32).map(|j| (0, j)).collect::<Vec<_>>();
+    let start = Instant::now();
+    let database = if args.additive { PatternDatabase::build_additive(args.n, &shape) } else { PatternDatabase::build(args.n, &shape) };
+    eprintln!("built n = {}, shape {:?}, additive: {}: max value {}, {:.2}s", args.n, shape, args.additive, database.max_value(), start.elapsed().as_secs_f64());
+
+    let suffix = if args.additive { "_additive" } else { "" };
+    let path = args.output.unwrap_or_else(|| PathBuf::from(format!("pdb/n{}_g{}{}.bin", args.n, args.group_size, suffix)));
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).expect("failed to create output directory");
+    }
+    database.save(&path).expect("failed to write pattern database");
+    eprintln!("wrote {}", path.display());
+}
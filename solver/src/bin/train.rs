@@ -0,0 +1,146 @@
+use clap::Parser;
+use solver::linear::{feature_name, features, LeastSquares, LinearModel, FEATURES};
+use solver::moves::parse_moves;
+use solver::{solve, Input, SolverConfig, State};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(about = "Fits the linear evaluator on the states of saved solutions")]
+struct Args {
+    /// directory with the in{n}.txt instances
+    #[arg(long, default_value = "input")]
+    input_dir: PathBuf,
+    /// directory with the out{n}.txt solutions to learn from
+    #[arg(long, default_value = "output")]
+    output_dir: PathBuf,
+    /// only learn from instances up to this size
+    #[arg(long)]
+    max_n: Option<usize>,
+    /// use one state out of this many along each solution
+    #[arg(long, default_value_t = 4)]
+    stride: usize,
+    /// exponent of the weighted_distance feature, kept in the model
+    #[arg(long, default_value_t = 1.7)]
+    distance_exponent: f64,
+    /// ridge penalty per sample
+    #[arg(long, default_value_t = 1e-6)]
+    ridge: f64,
+    /// hold out every this many-th solution, by instance number, to measure the model on
+    #[arg(long, default_value_t = 3)]
+    holdout: usize,
+    /// seconds per solve when comparing the lengths of the held-out instances with the default
+    /// evaluator; 0 skips the comparison
+    #[arg(long, default_value_t = 5.0)]
+    compare_time: f64,
+    /// weight kept as is instead of fitted, as `feature=weight` (repeatable)
+    #[arg(long, value_parser = parse_fixed)]
+    fix: Vec<(usize, f64)>,
+    /// where to write the model (stdout if omitted)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let mut instances = vec![];
+    for entry in std::fs::read_dir(&args.input_dir).expect("failed to read input directory") {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        let Some(id) = name.strip_prefix("in").and_then(|rest| rest.strip_suffix(".txt")) else {
+            continue;
+        };
+        instances.push((id.parse::<u64>().unwrap_or(u64::MAX), id.to_string(), path));
+    }
+    instances.sort();
+
+    // fitted on, and held out
+    let (mut least_squares, mut held_out) = (LeastSquares::default(), LeastSquares::default());
+    let (mut names, mut held_out_inputs) = (vec![], vec![]);
+    for (_, id, path) in instances {
+        let input = solver::input::parse_input(&std::fs::read_to_string(&path).unwrap());
+        if args.max_n.is_some_and(|max_n| input.n > max_n) {
+            continue;
+        }
+        let Ok(text) = std::fs::read_to_string(args.output_dir.join(format!("out{}.txt", id))) else {
+            continue;
+        };
+        let Ok(moves) = parse_moves(&text) else {
+            eprintln!("out{}.txt: invalid move, skipped", id);
+            continue;
+        };
+
+        let mut state = State::from_input(&input);
+        let weighted = state.geometry.weighted_distances(args.distance_exponent);
+        let mut samples = vec![];
+        for (t, &m) in moves.iter().enumerate() {
+            if t % args.stride.max(1) == 0 {
+                samples.push((features(&state, &weighted), (moves.len() - t) as f64));
+            }
+            state.apply(m);
+        }
+        if !state.is_solved() {
+            eprintln!("out{}.txt: does not solve the instance, skipped", id);
+            continue;
+        }
+        // every solution weighs the same in total, and errors count relative to its length
+        let weight = 1.0 / (samples.len() as f64 * (moves.len() * moves.len()) as f64);
+        let holdout = args.holdout != 0 && (names.len() + held_out_inputs.len()) % args.holdout == args.holdout - 1;
+        let target = if holdout { &mut held_out } else { &mut least_squares };
+        samples.iter().for_each(|(f, y)| target.add(f, *y, weight));
+        if holdout {
+            held_out_inputs.push((id, input, moves.len()));
+        } else {
+            names.push(id);
+        }
+    }
+    assert!(least_squares.count() > 0, "no solution to learn from");
+
+    let mut fixed = [None; FEATURES];
+    for &(k, weight) in args.fix.iter() {
+        fixed[k] = Some(weight);
+    }
+    let model = least_squares.solve(args.ridge, &fixed, args.distance_exponent);
+    let (rmse, r2) = least_squares.error(&model);
+    eprintln!("{} states from {} solutions: rmse {:.2} moves, r2 {:.4}", least_squares.count(), names.len(), rmse, r2);
+    if held_out.count() > 0 {
+        let (rmse, r2) = held_out.error(&model);
+        eprintln!("{} held-out states from {} solutions: rmse {:.2} moves, r2 {:.4}", held_out.count(), held_out_inputs.len(), rmse, r2);
+    }
+    for k in 0..FEATURES {
+        eprintln!("  {:>16} {:>10.4}", feature_name(k), model.weights[k]);
+    }
+    if args.compare_time > 0.0 && !held_out_inputs.is_empty() {
+        compare(&model, &held_out_inputs, args.compare_time);
+    }
+
+    let text = format!("# linear evaluator trained on {} states from {} solutions, rmse {:.2}\n{}", least_squares.count(), names.len(), rmse, model.format());
+    match &args.output {
+        Some(path) => std::fs::write(path, text).expect("failed to write model"),
+        None => print!("{}", text),
+    }
+}
+
+/// Solves the held-out instances, each with the saved solution length, with `model` and with the
+/// default evaluator, `seconds` each, and reports the lengths.
+fn compare(model: &LinearModel, inputs: &[(String, Input, usize)], seconds: f64) {
+    let config = SolverConfig { time_limit: Some(Duration::from_secs_f64(seconds)), ..SolverConfig::default() };
+    let with_model = SolverConfig { linear_model: Some(model.clone()), ..config.clone() };
+    let (mut total_model, mut total_default) = (0, 0);
+    for (id, input, saved) in inputs {
+        let by_model = solve(input, &with_model).moves.len();
+        let by_default = solve(input, &config).moves.len();
+        eprintln!("in{}.txt: {} moves with the model, {} with the default evaluator, {} saved", id, by_model, by_default, saved);
+        total_model += by_model;
+        total_default += by_default;
+    }
+    eprintln!("held-out total: {} moves with the model, {} with the default evaluator", total_model, total_default);
+}
+
+fn parse_fixed(text: &str) -> Result<(usize, f64), String> {
+    let (key, value) = text.split_once('=').ok_or("expected `feature=weight`")?;
+    let k = (0..FEATURES).find(|&k| feature_name(k) == key.trim()).ok_or(format!("unknown feature {:?}", key))?;
+    let weight = value.trim().parse().map_err(|_| format!("invalid weight {:?}", value))?;
+    Ok((k, weight))
+}
@@ -0,0 +1,197 @@
+use clap::{Parser, ValueEnum};
+use solver::params::{format_params, parse_params};
+use solver::{Input, SolverConfig};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Method {
+    /// sample every parameter uniformly in its range
+    Random,
+    /// move one parameter at a time, halving the steps when no move helps
+    Coordinate,
+}
+
+#[derive(Parser)]
+#[command(about = "Tunes the heuristic parameters on the sample instances")]
+struct Args {
+    /// directory with the in*.txt instances
+    #[arg(long, default_value = "input")]
+    input_dir: PathBuf,
+    /// only use instances up to this size
+    #[arg(long, default_value_t = 10)]
+    max_n: usize,
+    /// time budget of each solve in seconds
+    #[arg(short, long, default_value_t = 1.0)]
+    time_limit: f64,
+    /// maximum beam width of each solve; the time limit adapts the width below it
+    #[arg(short = 'w', long, default_value_t = 3000)]
+    beam_width: usize,
+    #[arg(short, long, value_enum, default_value_t = Method::Coordinate)]
+    method: Method,
+    /// number of parameter sets to evaluate
+    #[arg(short = 'n', long, default_value_t = 30)]
+    iterations: usize,
+    #[arg(short, long, default_value_t = 1)]
+    seed: u64,
+    /// starting parameters (defaults if omitted)
+    #[arg(short, long)]
+    params: Option<PathBuf>,
+    /// where to write the best parameters (stdout if omitted)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// worker threads of each solve
+    #[arg(short = 'j', long, default_value_t = 1)]
+    threads: usize,
+}
+
+struct Param {
+    name: &'static str,
+    min: f64,
+    max: f64,
+    step: f64,
+    integer: bool,
+}
+
+impl Param {
+    /// Smallest change `set` keeps.
+    fn resolution(&self) -> f64 {
+        if self.integer {
+            1.0
+        } else {
+            0.001
+        }
+    }
+}
+
+const SPACE: [Param; 4] = [
+    Param { name: "distance_exponent", min: 1.0, max: 2.5, step: 0.2, integer: false },
+    Param { name: "mismatch_weight", min: 0.0, max: 5.0, step: 0.5, integer: false },
+    Param { name: "diversity_cap", min: 1.0, max: 50.0, step: 4.0, integer: true },
+    Param { name: "candidate_factor", min: 1.0, max: 5.0, step: 0.5, integer: false },
+];
+
+fn get(config: &SolverConfig, idx: usize) -> f64 {
+    match idx {
+        0 => config.distance_exponent,
+        1 => config.mismatch_weight,
+        2 => config.diversity.cap() as f64,
+        _ => config.candidate_factor,
+    }
+}
+
+fn set(config: &mut SolverConfig, idx: usize, value: f64) {
+    let param = &SPACE[idx];
+    let value = value.clamp(param.min, param.max);
+    let value = if param.integer { value.round() } else { (value * 1000.).round() / 1000. };
+    match idx {
+        0 => config.distance_exponent = value,
+        1 => config.mismatch_weight = value,
+        2 => config.diversity = config.diversity.with_cap(value as usize),
+        _ => config.candidate_factor = value,
+    }
+}
+
+/// Total solution length over `inputs`. Under the time limit, `solver::solve` finishes a search
+/// that runs out of time constructively, so such an instance costs its long constructive tail.
+fn cost(inputs: &[Input], config: &SolverConfig) -> usize {
+    inputs.iter().map(|input| solver::solve(input, config).moves.len()).sum()
+}
+
+fn describe(config: &SolverConfig) -> String {
+    (0..SPACE.len()).map(|idx| format!("{}={}", SPACE[idx].name, get(config, idx))).collect::<Vec<_>>().join(" ")
+}
+
+struct Xorshift(u64);
+
+impl Xorshift {
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 << 7;
+        self.0 ^= self.0 >> 9;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let mut inputs = vec![];
+    for entry in std::fs::read_dir(&args.input_dir).expect("failed to read input directory") {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        if !name.starts_with("in") || !name.ends_with(".txt") {
+            continue;
+        }
+        let input = solver::input::parse_input(&std::fs::read_to_string(&path).unwrap());
+        if input.n <= args.max_n {
+            inputs.push(input);
+        }
+    }
+    inputs.sort_by_key(|input| input.n);
+    assert!(!inputs.is_empty(), "no instance with n <= {}", args.max_n);
+    eprintln!("instances: {:?}", inputs.iter().map(|input| input.n).collect::<Vec<_>>());
+
+    let mut best = SolverConfig {
+        beam_width: args.beam_width,
+        time_limit: Some(Duration::from_secs_f64(args.time_limit)),
+        threads: args.threads,
+        ..SolverConfig::default()
+    };
+    if let Some(path) = &args.params {
+        parse_params(&std::fs::read_to_string(path).expect("failed to read parameter file"), &mut best).unwrap();
+    }
+    let mut best_cost = cost(&inputs, &best);
+    eprintln!("start: {} -> {}", describe(&best), best_cost);
+
+    let mut rng = Xorshift(args.seed.max(1).wrapping_mul(0x9e3779b97f4a7c15) | 1);
+    let mut steps = SPACE.iter().map(|param| param.step).collect::<Vec<_>>();
+    let mut iteration = 1;
+    let mut improved_in_sweep = false;
+    let mut coordinate = 0;
+    while iteration < args.iterations {
+        let mut config = best.clone();
+        match args.method {
+            Method::Random => {
+                for (idx, param) in SPACE.iter().enumerate() {
+                    set(&mut config, idx, param.min + (param.max - param.min) * rng.next_f64());
+                }
+            }
+            Method::Coordinate => {
+                // try +step, then -step, on each parameter in turn
+                let idx = coordinate / 2 % SPACE.len();
+                let sign = if coordinate % 2 == 0 { 1.0 } else { -1.0 };
+                coordinate += 1;
+                if coordinate % (2 * SPACE.len()) == 0 {
+                    if !improved_in_sweep {
+                        steps.iter_mut().for_each(|step| *step /= 2.0);
+                    }
+                    improved_in_sweep = false;
+                }
+                // once every step rounds to no change, no try is left
+                if steps.iter().zip(SPACE.iter()).all(|(&step, param)| step < param.resolution() / 2.0) {
+                    eprintln!("{}: every step is below the resolution of its parameter", iteration);
+                    break;
+                }
+                set(&mut config, idx, get(&best, idx) + sign * steps[idx]);
+                if get(&config, idx) == get(&best, idx) {
+                    continue;
+                }
+            }
+        }
+        let c = cost(&inputs, &config);
+        eprintln!("{}: {} -> {}", iteration, describe(&config), c);
+        if c < best_cost {
+            best_cost = c;
+            best = config;
+            improved_in_sweep = true;
+        }
+        iteration += 1;
+    }
+
+    eprintln!("best: {} -> {}", describe(&best), best_cost);
+    let text = format!("# tuned on n <= {} with {}s per solve, total length {}\n{}", args.max_n, args.time_limit, best_cost, format_params(&best));
+    match &args.output {
+        Some(path) => std::fs::write(path, text).expect("failed to write output"),
+        None => print!("{}", text),
+    }
+}
@@ -0,0 +1,32 @@
+use crate::state::State;
+
+/// Number of moves that any solution from `state` needs at least.
+///
+/// A move shifts the blank by one cell and two other tiles by one cell each, so it lowers the
+/// total torus distance of the non-blank tiles by at most 2 and the distance of the blank by at
+/// most 1. The bound is the larger of the two resulting counts.
+pub fn lower_bound(state: &State) -> u32 {
+    let tiles = (1..state.tile_positions.len()).map(|i| state.raw_distance(i)).sum::<u32>();
+    tiles.div_ceil(2).max(state.raw_distance(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Input;
+    use crate::utils::random_moves;
+
+    #[test]
+    fn bounds_short_scrambles() {
+        for n in [3, 5, 8] {
+            assert_eq!(lower_bound(&State::from_input(&Input::solved(n))), 0, "n = {}: solved board", n);
+            for k in 1..=20 {
+                for seed in 0..5 {
+                    let state = State::from_input(&Input::scrambled(n, &random_moves(k, seed)));
+                    let bound = lower_bound(&state);
+                    assert!(0 < bound && bound as usize <= k, "n = {}: bound {} after {} moves", n, bound, k);
+                }
+            }
+        }
+    }
+}
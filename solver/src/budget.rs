@@ -0,0 +1,108 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Chooses the beam width of each depth so that the search ends before the deadline.
+///
+/// The cost of one node is measured on every depth, and the number of remaining depths is
+/// extrapolated from how fast the best score has been decreasing so far. A narrow beam can get
+/// stuck, so the width is doubled whenever the best score stops improving, even past the planned
+/// share of the limit. A depth that would end past the limit runs at `min_width`; the search stops
+/// at the limit, and `solve` finishes it constructively.
+///
+/// [`solve`]: crate::solve
+pub struct TimeBudget {
+    start: Instant,
+    limit: Duration,
+    min_width: usize,
+    max_width: usize,
+    sec_per_node: f64,
+    initial_dist: i64,
+    history: VecDeque<i64>,
+    best_dist: i64,
+    last_improved: usize,
+    boost: usize,
+}
+
+const PATIENCE: usize = 30;
+const WINDOW: usize = 100;
+/// Share of the limit the widths are planned for; the rest absorbs the slow last depths, where
+/// the best score decreases more slowly than the extrapolation assumes.
+const PLANNED: f64 = 0.7;
+
+impl TimeBudget {
+    pub fn new(limit: Duration, min_width: usize, max_width: usize) -> Self {
+        TimeBudget {
+            start: Instant::now(),
+            limit,
+            min_width: min_width.max(1),
+            max_width: max_width.max(1),
+            sec_per_node: 0.0,
+            initial_dist: -1,
+            history: VecDeque::with_capacity(WINDOW + 1),
+            best_dist: i64::MAX,
+            last_improved: 0,
+            boost: 1,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// The instant by which `share` of the time left has passed.
+    pub fn deadline(&self, share: f64) -> Instant {
+        Instant::now() + self.limit.saturating_sub(self.start.elapsed()).mul_f64(share)
+    }
+
+    /// Whether the limit has passed; the search then stops.
+    pub fn expired(&self) -> bool {
+        self.start.elapsed() >= self.limit
+    }
+
+    /// `nodes` leaves were expanded at depth `t` in `depth_time`, and the best score is now `dist`.
+    pub fn next_width(&mut self, t: usize, width: usize, nodes: usize, depth_time: Duration, dist: i64) -> usize {
+        if self.initial_dist < 0 {
+            self.initial_dist = dist;
+        }
+        self.history.push_back(dist);
+        if self.history.len() > WINDOW {
+            self.history.pop_front();
+        }
+        if dist < self.best_dist {
+            self.best_dist = dist;
+            self.last_improved = t;
+        } else if t - self.last_improved > PATIENCE {
+            self.last_improved = t;
+            self.boost = (self.boost * 2).min(self.max_width);
+        }
+        let sec_per_node = depth_time.as_secs_f64() / nodes.max(1) as f64;
+        self.sec_per_node = if self.sec_per_node == 0.0 { sec_per_node } else { 0.9 * self.sec_per_node + 0.1 * sec_per_node };
+
+        let elapsed = self.start.elapsed().as_secs_f64();
+        // a depth at `width` that would end past the limit is not started wider than `min_width`
+        let fits = |width: usize| elapsed + width as f64 * self.sec_per_node < self.limit.as_secs_f64();
+        let boosted = (self.min_width * self.boost).min(self.max_width);
+        let remaining_time = PLANNED * self.limit.as_secs_f64() - elapsed;
+        let width = if remaining_time <= 0.0 {
+            boosted
+        } else {
+            // the slower of the overall and the recent progress
+            let overall_rate = (self.initial_dist - dist) as f64 / t as f64;
+            let recent_rate = (self.history[0] - dist) as f64 / self.history.len() as f64;
+            let rate = if self.history.len() == WINDOW { overall_rate.min(recent_rate) } else { overall_rate };
+            if rate <= 0.0 {
+                width.max(boosted)
+            } else {
+                let remaining_depths = dist as f64 / rate + 1.0;
+                let target = remaining_time / (remaining_depths * self.sec_per_node);
+                let target = target.min(width as f64 * 1.5).round() as usize;
+                target.clamp(boosted, self.max_width)
+            }
+        };
+        if fits(width) {
+            width
+        } else {
+            self.min_width
+        }
+    }
+}
@@ -0,0 +1,488 @@
+use crate::geometry::Geometry;
+use crate::moves::{Move, DIRECTIONS};
+use crate::state::State;
+use crate::{solve, Input, Solution, SolverConfig};
+use std::collections::VecDeque;
+use std::time::Instant;
+
+type Offset = (i32, i32);
+type Cell = (usize, usize);
+
+/// A commutator `L1 L2 L1^-1 L2^-1` of two loops of the blank whose moved cells share exactly
+/// one: it brings the blank back and cycles three cells, given as offsets from the blank. The
+/// tile at `cycle[k]` goes to `cycle[k + 1]`.
+struct Macro {
+    moves: Vec<Move>,
+    cycle: [Offset; 3],
+}
+
+/// Longest loop a macro is built from; loops of 4 moves already give every 3-cycle needed.
+const LOOP_LENGTH: usize = 4;
+
+/// The move of the blank one step in direction `d`, clockwise or not.
+fn step(d: usize, clockwise: bool) -> Move {
+    if clockwise {
+        Move::from_index(d)
+    } else {
+        Move::from_index(6 + (d + 5) % 6)
+    }
+}
+
+fn add((i, j): Offset, (di, dj): Offset) -> Offset {
+    (i + di, j + dj)
+}
+
+fn invert(moves: &[Move]) -> Vec<Move> {
+    moves.iter().rev().map(|m| m.inverse()).collect()
+}
+
+/// Cells changed by `moves` from the blank at the origin of the unwrapped lattice, each with the
+/// cell its tile came from, and the final offset of the blank.
+pub(crate) fn lattice_effect(moves: &[Move]) -> (Vec<(Offset, Offset)>, Offset) {
+    let mut origin = rustc_hash::FxHashMap::default();
+    let mut blank = (0, 0);
+    for &m in moves {
+        let (b, c) = m.neighbors();
+        let (b, c) = (add(blank, b), add(blank, c));
+        let from = |cell| *origin.get(&cell).unwrap_or(&cell);
+        let (at_a, at_b, at_c) = (from(blank), from(b), from(c));
+        origin.insert(blank, at_c);
+        origin.insert(b, at_a);
+        origin.insert(c, at_b);
+        blank = b;
+    }
+    (origin.into_iter().filter(|(cell, from)| cell != from).collect(), blank)
+}
+
+/// Every loop of the blank of even length up to `LOOP_LENGTH` that starts with a move of
+/// direction `clockwise` and changes the board.
+fn loops(clockwise: bool) -> Vec<Vec<Move>> {
+    fn extend(path: &mut Vec<Move>, blank: Offset, clockwise: bool, loops: &mut Vec<Vec<Move>>) {
+        if !path.is_empty() && path.len().is_multiple_of(2) && blank == (0, 0) && !lattice_effect(path).0.is_empty() {
+            loops.push(path.clone());
+        }
+        if path.len() == LOOP_LENGTH {
+            return;
+        }
+        for m in Move::ALL {
+            if m.is_clockwise() != clockwise || path.last().is_some_and(|&last| last.inverse() == m) {
+                continue;
+            }
+            path.push(m);
+            extend(path, add(blank, m.neighbors().0), !clockwise, loops);
+            path.pop();
+        }
+    }
+    let mut loops = vec![];
+    extend(&mut vec![], (0, 0), clockwise, &mut loops);
+    loops
+}
+
+/// The shortest macro for every 3-cycle the loops starting with `clockwise` give.
+fn macros(clockwise: bool) -> Vec<Macro> {
+    let loops: Vec<_> = loops(clockwise).into_iter().map(|moves| (lattice_effect(&moves).0, moves)).collect();
+    let mut macros: Vec<Macro> = vec![];
+    for (effect1, loop1) in loops.iter() {
+        for (effect2, loop2) in loops.iter() {
+            if effect1.iter().filter(|(cell, _)| effect2.iter().any(|(other, _)| other == cell)).count() != 1 {
+                continue;
+            }
+            let moves: Vec<_> = [loop1.clone(), loop2.clone(), invert(loop1), invert(loop2)].concat();
+            let (effect, _) = lattice_effect(&moves);
+            if effect.len() != 3 {
+                continue;
+            }
+            // the tile from `effect[k].1` goes to `effect[k].0`
+            let next = |cell: Offset| effect.iter().find(|(_, from)| *from == cell).unwrap().0;
+            let first = effect.iter().map(|&(cell, _)| cell).min().unwrap();
+            let cycle = [first, next(first), next(next(first))];
+            match macros.iter_mut().find(|other| other.cycle == cycle) {
+                Some(other) if other.moves.len() <= moves.len() => {}
+                Some(other) => other.moves = moves,
+                None => macros.push(Macro { moves, cycle }),
+            }
+        }
+    }
+    macros.sort_by_key(|m| m.moves.len());
+    macros
+}
+
+/// Places the tiles one at a time with 3-cycles, each a `Macro` brought to its place by a walk
+/// of the blank from its target cell (`home`) and back. A walk and the walk back of the next
+/// 3-cycle mostly cancel, see `push`.
+struct Builder {
+    geometry: std::sync::Arc<Geometry>,
+    home: Cell,
+    /// tile at each cell id, and the cell id of each tile
+    board: Vec<usize>,
+    positions: Vec<usize>,
+    targets: Vec<usize>,
+    /// cell ids holding their final tile
+    placed: Vec<bool>,
+    moves: Vec<Move>,
+    /// the move before `moves`, if any
+    before: Option<Move>,
+    /// by starting direction: anticlockwise, clockwise
+    macros: [Vec<Macro>; 2],
+    /// direction every 3-cycle starts with, so that consecutive walks cancel
+    clockwise: bool,
+    /// 3-cycles by the cell ids of the tile they move, see `index_cycles`
+    shifts: rustc_hash::FxHashMap<(usize, usize), Vec<Shift>>,
+}
+
+/// A 3-cycle that moves a tile from one cell to another: macro `k` of those starting with
+/// `clockwise`, run with the blank at `w`, and the cell id of the third cell it moves.
+struct Shift {
+    w: Cell,
+    clockwise: bool,
+    k: usize,
+    third: usize,
+    cost: usize,
+}
+
+/// A 3-cycle found for `Builder::find`: the moves, then the cells it cycles.
+type Cycle = (Vec<Move>, [Cell; 3]);
+
+impl Builder {
+    fn new(state: &State, before: Option<Move>) -> Self {
+        let geometry = state.geometry.clone();
+        let id = |cell| geometry.cell_id(cell);
+        let mut board = vec![0; geometry.cells().len()];
+        for (tile, &cell) in state.tile_positions.iter().enumerate() {
+            board[id(cell)] = tile;
+        }
+        Builder {
+            home: state.target_positions[0],
+            positions: state.tile_positions.iter().map(|&cell| id(cell)).collect(),
+            targets: state.target_positions.iter().map(|&cell| id(cell)).collect(),
+            placed: vec![false; board.len()],
+            board,
+            moves: vec![],
+            before,
+            macros: [macros(false), macros(true)],
+            clockwise: true,
+            shifts: Default::default(),
+            geometry,
+        }
+    }
+
+    fn last(&self) -> Option<Move> {
+        self.moves.last().copied().or(self.before)
+    }
+
+    /// Appends `m`, or drops the last move if `m` undoes it.
+    fn push(&mut self, m: Move) {
+        let blank = self.geometry.cells()[self.positions[0]];
+        let (b, c) = self.geometry.rotated_cells(blank, m);
+        let (a, b, c) = (self.positions[0], self.geometry.cell_id(b), self.geometry.cell_id(c));
+        let (x, y) = (self.board[b], self.board[c]);
+        self.board[a] = y;
+        self.board[b] = 0;
+        self.board[c] = x;
+        self.positions[y] = a;
+        self.positions[0] = b;
+        self.positions[x] = c;
+        if self.moves.last() == Some(&m.inverse()) {
+            self.moves.pop();
+        } else {
+            self.moves.push(m);
+        }
+    }
+
+    /// Directions of the walk from `a` to `b`: straight along one direction, then along the next
+    /// one clockwise.
+    fn path(&self, a: Cell, b: Cell) -> Vec<usize> {
+        let (di, dj) = self.geometry.offset(a, b);
+        for d in 0..6 {
+            let ((i1, j1), (i2, j2)) = (DIRECTIONS[d], DIRECTIONS[(d + 1) % 6]);
+            let det = i1 * j2 - j1 * i2;
+            let (s, t) = ((di * j2 - dj * i2) / det, (i1 * dj - j1 * di) / det);
+            if s >= 0 && t >= 0 && add((s * i1, s * j1), (t * i2, t * j2)) == (di, dj) {
+                return [vec![d; s as usize], vec![(d + 1) % 6; t as usize]].concat();
+            }
+        }
+        unreachable!()
+    }
+
+    /// Walks the blank to `home`, before the first 3-cycle.
+    fn walk_home(&mut self) {
+        let blank = self.geometry.cells()[self.positions[0]];
+        for d in self.path(blank, self.home) {
+            self.push(step(d, self.last().is_none_or(|last| !last.is_clockwise())));
+        }
+        self.clockwise = self.last().is_none_or(|last| !last.is_clockwise());
+    }
+
+    /// The walk to `w`, macro `k` of those starting with `clockwise` and the walk back, or `None`
+    /// if the macro cannot follow that walk.
+    fn conjugate(&self, w: Cell, clockwise: bool, k: usize) -> Option<Vec<Move>> {
+        let path = self.path(self.home, w);
+        if (path.len() % 2 == 1) == (self.clockwise == clockwise) {
+            return None;
+        }
+        let mut moves: Vec<Move> = path.iter().enumerate().map(|(t, &d)| step(d, self.clockwise == (t % 2 == 0))).collect();
+        let back = invert(&moves);
+        moves.extend(self.macros[clockwise as usize][k].moves.iter().copied());
+        moves.extend(back);
+        Some(moves)
+    }
+
+    /// The cell ids `moves` cycle from the blank at `home`: the tile at `cycle[k]` goes to
+    /// `cycle[k + 1]`. The walk there and back is a conjugation, so this is a 3-cycle like the
+    /// macro, but not always of the cells of the macro: the walk may have moved them. `origin`
+    /// maps every cell id to itself, and is left that way.
+    fn cycle_of(&self, moves: &[Move], origin: &mut [usize]) -> [usize; 3] {
+        let id = |cell| self.geometry.cell_id(cell);
+        let mut touched = vec![];
+        let mut blank = self.home;
+        for &m in moves {
+            let (b, c) = self.geometry.rotated_cells(blank, m);
+            let (a, b, c) = (id(blank), id(b), id(c));
+            (origin[a], origin[b], origin[c]) = (origin[c], origin[a], origin[b]);
+            touched.extend([a, b, c]);
+            blank = self.geometry.cells()[b];
+        }
+        let mut changed = vec![];
+        for cell in touched {
+            if origin[cell] != cell {
+                changed.push((cell, origin[cell]));
+                origin[cell] = cell;
+            }
+        }
+        debug_assert!(blank == self.home && changed.len() == 3);
+        let next = |cell| changed.iter().find(|&&(_, from)| from == cell).unwrap().0;
+        let first = changed[0].0;
+        [first, next(first), next(next(first))]
+    }
+
+    /// Indexes the 3-cycle of every macro at every cell by the moves of single tiles it makes,
+    /// cheapest first.
+    fn index_cycles(&mut self) {
+        let mut shifts: rustc_hash::FxHashMap<(usize, usize), Vec<Shift>> = Default::default();
+        let mut origin: Vec<usize> = (0..self.board.len()).collect();
+        for &w in self.geometry.cells() {
+            for clockwise in [false, true] {
+                for k in 0..self.macros[clockwise as usize].len() {
+                    let Some(moves) = self.conjugate(w, clockwise, k) else {
+                        continue;
+                    };
+                    let cycle = self.cycle_of(&moves, &mut origin);
+                    for t in 0..3 {
+                        let shift = Shift { w, clockwise, k, third: cycle[(t + 2) % 3], cost: moves.len() };
+                        shifts.entry((cycle[t], cycle[(t + 1) % 3])).or_default().push(shift);
+                    }
+                }
+            }
+        }
+        for list in shifts.values_mut() {
+            list.sort_by_key(|shift| shift.cost);
+        }
+        self.shifts = shifts;
+    }
+
+    /// A 3-cycle moving the tile at `x` to its neighbor `y`, whose third cell passes `allowed`.
+    fn find(&self, x: Cell, y: Cell, allowed: impl Fn(Cell) -> bool) -> Option<Cycle> {
+        let id = |cell| self.geometry.cell_id(cell);
+        let shift = self.shifts.get(&(id(x), id(y)))?.iter().find(|shift| allowed(self.geometry.cells()[shift.third]))?;
+        Some((self.conjugate(shift.w, shift.clockwise, shift.k).unwrap(), [x, y, self.geometry.cells()[shift.third]]))
+    }
+
+    fn apply(&mut self, moves: &[Move]) {
+        for &m in moves {
+            self.push(m);
+        }
+    }
+
+    /// Shortest path from `a` to `b` through the cells passing `open`, `a` and `b` excluded.
+    fn route(&self, a: Cell, b: Cell, open: impl Fn(Cell) -> bool) -> Option<Vec<Cell>> {
+        let id = |cell| self.geometry.cell_id(cell);
+        let mut parent = vec![usize::MAX; self.board.len()];
+        parent[id(a)] = id(a);
+        let mut queue = VecDeque::from([a]);
+        while let Some(cell) = queue.pop_front() {
+            if cell == b {
+                let mut path = vec![b];
+                while *path.last().unwrap() != a {
+                    path.push(self.geometry.cells()[parent[id(*path.last().unwrap())]]);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for &next in self.geometry.neighbors(cell) {
+                if parent[id(next)] == usize::MAX && (next == b || open(next)) {
+                    parent[id(next)] = id(cell);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    /// Moves `tile` to `goal` step by step, with 3-cycles that only touch cells not yet placed.
+    fn transport(&mut self, tile: usize, goal: Cell) -> bool {
+        let start = self.geometry.cells()[self.positions[tile]];
+        let home = self.home;
+        let placed = self.placed.clone();
+        let geometry = self.geometry.clone();
+        let free = |cell| cell != home && !placed[geometry.cell_id(cell)];
+        let Some(route) = self.route(start, goal, free) else {
+            return false;
+        };
+        for pair in route.windows(2) {
+            let Some((moves, _)) = self.find(pair[0], pair[1], free) else {
+                return false;
+            };
+            self.apply(&moves);
+        }
+        true
+    }
+
+    /// Moves `tile` to `goal` with a 3-cycle of its cell, `goal` and a free cell next to `goal`:
+    /// a 3-cycle `(d, goal, free)` conjugated by a sequence that brings the tile to `d`, which may
+    /// pass through placed cells since it is undone.
+    fn exchange(&mut self, tile: usize, goal: Cell) -> bool {
+        let start = self.geometry.cells()[self.positions[tile]];
+        let home = self.home;
+        let geometry = self.geometry.clone();
+        for &d in geometry.neighbors(goal) {
+            if d == home {
+                continue;
+            }
+            let placed = self.placed.clone();
+            let Some((middle, [_, _, third])) = self.find(d, goal, |cell| cell != start && !placed[geometry.cell_id(cell)]) else {
+                continue;
+            };
+            let Some(route) = self.route(start, d, |cell| cell != home && cell != goal && cell != third) else {
+                continue;
+            };
+            let mut setup = vec![];
+            for pair in route.windows(2) {
+                let Some((moves, _)) = self.find(pair[0], pair[1], |cell| cell != goal && cell != third) else {
+                    break;
+                };
+                self.apply(&moves);
+                setup.push(moves);
+            }
+            if setup.len() + 1 < route.len() {
+                for moves in setup.iter().rev() {
+                    self.apply(&invert(moves));
+                }
+                continue;
+            }
+            self.apply(&middle);
+            for moves in setup.iter().rev() {
+                self.apply(&invert(moves));
+            }
+            return true;
+        }
+        false
+    }
+
+    /// Places every tile, farthest target from `home` first, so that the free cells stay a ball
+    /// around it. Fails if the last two tiles are swapped, which no sequence of moves can fix
+    /// since every move is an even permutation.
+    fn solve(&mut self) -> bool {
+        self.walk_home();
+        self.index_cycles();
+        let geometry = self.geometry.clone();
+        let mut order: Vec<usize> = (1..self.targets.len()).collect();
+        order.sort_by_key(|&tile| std::cmp::Reverse(geometry.distance(self.home, geometry.cells()[self.targets[tile]])));
+        for tile in order {
+            let goal = geometry.cells()[self.targets[tile]];
+            if self.positions[tile] != self.targets[tile] && !self.transport(tile, goal) && !self.exchange(tile, goal) {
+                return false;
+            }
+            self.placed[self.targets[tile]] = true;
+        }
+        true
+    }
+}
+
+/// Moves that solve `state`, whose last move was `before`, or `None` if it cannot be solved.
+pub fn solve_from(state: &State, before: Option<Move>) -> Option<Vec<Move>> {
+    let mut builder = Builder::new(state, before);
+    builder.solve().then_some(builder.moves)
+}
+
+/// Deterministic solver that always finishes: it brings the blank to its target, then places
+/// the tiles one at a time with 3-cycles (see `Macro`). Each 3-cycle costs at most 16 moves plus
+/// twice the diameter of the board, and each tile takes O(n) of them, so a solution has O(n^4)
+/// moves; about 1.8 n^4 at n = 27.
+pub fn solve_constructive(input: &Input, config: &SolverConfig) -> Solution {
+    let start = Instant::now();
+    let state = State::from_input(input);
+    let moves = solve_from(&state, None);
+    if config.verbose >= 1 {
+        match &moves {
+            Some(moves) => eprintln!("constructive: {} moves, {:.2}s", moves.len(), start.elapsed().as_secs_f64()),
+            None => eprintln!("constructive: no 3-cycle places the last tiles, {:.2}s", start.elapsed().as_secs_f64()),
+        }
+    }
+    let solved = moves.is_some();
+    Solution { moves: moves.unwrap_or_default(), solved, score: 0, peak_nodes: 0, optimal: false }
+}
+
+/// `solve` without `fallback`, bounded by the constructive solution: the search stops at its
+/// length, and a search that ends unsolved is finished from its best state by `solve_from`.
+pub fn solve_with_fallback(input: &Input, config: &SolverConfig) -> Solution {
+    let bound = solve_constructive(input, config);
+    let max_depth = match bound.solved {
+        true => Some(config.max_depth.map_or(bound.moves.len(), |max_depth| max_depth.min(bound.moves.len()))),
+        false => config.max_depth,
+    };
+    let solution = solve(input, &SolverConfig { fallback: false, simplify: None, max_depth, ..config.clone() });
+    let solution = finish(input, solution, config);
+    match solution.solved {
+        true if !bound.solved || solution.moves.len() <= bound.moves.len() => solution,
+        _ if bound.solved => bound,
+        _ => solution,
+    }
+}
+
+/// `solution` if it is solved, else its moves followed by `solve_from` their end.
+pub fn finish(input: &Input, solution: Solution, config: &SolverConfig) -> Solution {
+    if solution.solved {
+        return solution;
+    }
+    let mut state = State::from_input(input);
+    for &m in solution.moves.iter() {
+        state.apply(m);
+    }
+    let finished = solve_from(&state, solution.moves.last().copied()).map(|tail| [solution.moves.clone(), tail].concat());
+    if config.verbose >= 1 {
+        match &finished {
+            Some(moves) => eprintln!("constructive: finished the search in {} moves", moves.len()),
+            None => eprintln!("constructive: no 3-cycle places the last tiles of the search"),
+        }
+    }
+    match finished {
+        Some(moves) => Solution { moves, solved: true, score: 0, ..solution },
+        None => solution,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::random_moves;
+
+    #[test]
+    fn solves_random_boards() {
+        for n in 3..=8 {
+            for seed in 0..3 {
+                let scramble = random_moves(20 * n * n, seed);
+                let input = Input::scrambled(n, &scramble);
+                let state = State::from_input(&input);
+                // from the start, and after the last move of a search
+                for before in [None, scramble.last().copied()] {
+                    let moves = solve_from(&state, before).unwrap_or_else(|| panic!("n = {}, seed {}: not solved", n, seed));
+                    let mut state = state.clone();
+                    moves.iter().for_each(|&m| state.apply(m));
+                    assert!(state.is_solved(), "n = {}, seed {}: the moves do not solve the board", n, seed);
+                    let alternate = before.iter().chain(moves.iter()).collect::<Vec<_>>().windows(2).all(|w| w[0].is_clockwise() != w[1].is_clockwise());
+                    assert!(alternate, "n = {}, seed {}, after {:?}: moves do not alternate", n, seed, before);
+                }
+            }
+        }
+    }
+}
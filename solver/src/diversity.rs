@@ -0,0 +1,102 @@
+use crate::moves::Move;
+use crate::state::State;
+use crate::utils::mix;
+use std::fmt;
+use std::str::FromStr;
+
+/// Number of buckets of `DiversityPolicy::Region`.
+const REGION_BUCKETS: usize = 1 << 16;
+
+/// How the beam search keeps its candidates apart: among the candidates with the same key, only
+/// the best `cap` are kept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiversityPolicy {
+    /// one key per cell of the blank
+    BlankCell { cap: usize },
+    /// one key per cell of the blank and last move
+    BlankCellAndMove { cap: usize },
+    /// a hash bucket of the blank cell and the tiles within `radius` of it
+    Region { radius: u32, cap: usize },
+}
+
+impl Default for DiversityPolicy {
+    fn default() -> Self {
+        DiversityPolicy::BlankCell { cap: 10 }
+    }
+}
+
+impl DiversityPolicy {
+    pub fn cap(&self) -> usize {
+        match *self {
+            DiversityPolicy::BlankCell { cap } | DiversityPolicy::BlankCellAndMove { cap } | DiversityPolicy::Region { cap, .. } => cap,
+        }
+    }
+
+    pub fn with_cap(self, cap: usize) -> Self {
+        match self {
+            DiversityPolicy::BlankCell { .. } => DiversityPolicy::BlankCell { cap },
+            DiversityPolicy::BlankCellAndMove { .. } => DiversityPolicy::BlankCellAndMove { cap },
+            DiversityPolicy::Region { radius, .. } => DiversityPolicy::Region { radius, cap },
+        }
+    }
+
+    /// Number of distinct keys on a board with `cells` cells; every key is below it.
+    pub fn keys(&self, cells: usize) -> usize {
+        match self {
+            DiversityPolicy::BlankCell { .. } => cells,
+            DiversityPolicy::BlankCellAndMove { .. } => cells * Move::ALL.len(),
+            DiversityPolicy::Region { .. } => REGION_BUCKETS,
+        }
+    }
+
+    /// Key of `state`, reached by `last`.
+    pub fn key(&self, state: &State, last: Move) -> usize {
+        let geometry = &state.geometry;
+        let blank = state.zero_position;
+        match *self {
+            DiversityPolicy::BlankCell { .. } => geometry.cell_id(blank),
+            DiversityPolicy::BlankCellAndMove { .. } => geometry.cell_id(blank) * Move::ALL.len() + last.index(),
+            DiversityPolicy::Region { radius, .. } => {
+                let mut hash = mix(geometry.cell_id(blank) as u64);
+                for &offset in geometry.offsets_by_distance() {
+                    let (i, j) = geometry.translate(blank, offset);
+                    if geometry.distance(blank, (i, j)) > radius {
+                        break;
+                    }
+                    hash = mix(hash ^ state.board[i][j] as u64);
+                }
+                (hash % REGION_BUCKETS as u64) as usize
+            }
+        }
+    }
+}
+
+/// `cell`, `cell-move`, `region` (radius 2) or `region:<radius>`, each with the cap that did best
+/// on the small instances (see `with_cap`).
+impl FromStr for DiversityPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "cell" => Ok(DiversityPolicy::BlankCell { cap: 10 }),
+            None if s == "cell-move" => Ok(DiversityPolicy::BlankCellAndMove { cap: 2 }),
+            None if s == "region" => Ok(DiversityPolicy::Region { radius: 2, cap: 1 }),
+            Some(("region", radius)) => match radius.parse() {
+                Ok(radius) => Ok(DiversityPolicy::Region { radius, cap: 1 }),
+                Err(_) => Err(format!("invalid region radius {:?}", radius)),
+            },
+            _ => Err(format!("unknown diversity policy {:?} (expected cell, cell-move or region[:radius])", s)),
+        }
+    }
+}
+
+/// The format read by `from_str`, without the cap.
+impl fmt::Display for DiversityPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiversityPolicy::BlankCell { .. } => write!(f, "cell"),
+            DiversityPolicy::BlankCellAndMove { .. } => write!(f, "cell-move"),
+            DiversityPolicy::Region { radius, .. } => write!(f, "region:{}", radius),
+        }
+    }
+}
@@ -0,0 +1,387 @@
+use crate::hash::HashKind;
+use crate::moves::Move;
+use crate::state::State;
+use crate::{Input, SolverConfig};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+const MAGIC: &[u8; 4] = b"BEGT";
+
+/// States within `depth` moves of the solved board, each with its exact distance to it; saved,
+/// it is the endgame table the beam search finishes with.
+///
+/// Moves are reversible and keep alternating, so the states reached by `k` moves from the solved
+/// board, with their last move, are the states that reach it in `k` moves. The hash covers the
+/// direction of the last move, so the start of a search, which has none, is never found.
+pub struct Perimeter {
+    n: usize,
+    hash: HashKind,
+    distances: rustc_hash::FxHashMap<u64, u32>,
+    depth: u32,
+}
+
+impl Perimeter {
+    /// Deepens the perimeter one move at a time while the next level likely stays within `limit`
+    /// states.
+    pub fn build(input: &Input, hash: HashKind, limit: usize) -> Self {
+        Perimeter::build_until(input, hash, limit, None)
+    }
+
+    /// `build`, also stopping before a level that would likely end past `deadline`: each level
+    /// has about five times the states of the one before, and takes about five times as long.
+    fn build_until(input: &Input, hash: HashKind, limit: usize, deadline: Option<Instant>) -> Self {
+        let mut perimeter = Perimeter { n: input.n, hash, distances: Default::default(), depth: 0 };
+        let mut level_time = Duration::ZERO;
+        while perimeter.distances.len() * 5 < limit {
+            let level_start = Instant::now();
+            if deadline.is_some_and(|deadline| level_start + level_time * 5 >= deadline) {
+                break;
+            }
+            perimeter.deepen(input, perimeter.depth + 1);
+            level_time = level_start.elapsed();
+        }
+        perimeter
+    }
+
+    /// Every state within `depth` moves of the solved board of size `n`.
+    pub fn build_to_depth(n: usize, hash: HashKind, depth: u32) -> Self {
+        let mut perimeter = Perimeter { n, hash, distances: Default::default(), depth: 0 };
+        perimeter.deepen(&Input::solved(n), depth);
+        perimeter
+    }
+
+    fn deepen(&mut self, input: &Input, depth: u32) {
+        let (_, targets) = input.positions();
+        let mut goal = State::new(input, targets.clone(), targets);
+        goal.set_hash(self.hash);
+        self.depth = depth;
+        let mut expanded = rustc_hash::FxHashSet::default();
+        // the solved board reached by a move of either direction: a move followed by its inverse
+        for m in [Move::Cw1, Move::CcwA] {
+            let mut state = goal.clone();
+            state.apply(m);
+            state.apply(m.inverse());
+            self.fill(&mut state, &mut expanded, 0, m.inverse());
+        }
+    }
+
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    pub fn hash(&self) -> HashKind {
+        self.hash
+    }
+
+    /// Whether the table was built for boards of size `n` hashed with `hash`.
+    pub fn fits(&self, n: usize, hash: HashKind) -> bool {
+        self.n == n && self.hash == hash
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    pub fn len(&self) -> usize {
+        self.distances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.distances.is_empty()
+    }
+
+    /// Moves from `state` to the solved board, if it is within `depth` of it.
+    pub fn distance(&self, state: &State) -> Option<u32> {
+        self.distances.get(&state.hash).copied()
+    }
+
+    /// A shortest sequence from `state`, reached by `last`, to the solved board, or `None` if
+    /// `state` is not in the perimeter or a hash collision led astray.
+    pub fn path_to_goal(&self, state: &State, last: Move) -> Option<Vec<Move>> {
+        let mut state = state.clone();
+        let mut last = last;
+        let mut distance = self.distance(&state)?;
+        let mut path = vec![];
+        while distance != 0 {
+            let next = Move::ALL.into_iter().filter(|m| m.is_clockwise() != last.is_clockwise()).find(|&m| {
+                state.apply(m);
+                let found = self.distance(&state) == Some(distance - 1);
+                if !found {
+                    state.revert(m);
+                }
+                found
+            })?;
+            path.push(next);
+            last = next;
+            distance -= 1;
+        }
+        state.is_solved().then_some(path)
+    }
+
+    /// Writes the hashes in increasing order, then their distances, one byte each.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&(self.n as u32).to_le_bytes())?;
+        writer.write_all(&(self.hash as u32).to_le_bytes())?;
+        writer.write_all(&self.depth.to_le_bytes())?;
+        writer.write_all(&(self.distances.len() as u64).to_le_bytes())?;
+        let mut entries: Vec<_> = self.distances.iter().map(|(&hash, &distance)| (hash, distance)).collect();
+        entries.sort_unstable();
+        for &(hash, _) in entries.iter() {
+            writer.write_all(&hash.to_le_bytes())?;
+        }
+        let distances: Vec<u8> = entries.iter().map(|&(_, distance)| distance as u8).collect();
+        writer.write_all(&distances)?;
+        writer.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut reader = io::BufReader::new(std::fs::File::open(path)?);
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let mut word = [0; 4];
+        reader.read_exact(&mut word)?;
+        if &word != MAGIC {
+            return Err(invalid("not an endgame table"));
+        }
+        let mut read_u32 = || -> io::Result<u32> {
+            reader.read_exact(&mut word)?;
+            Ok(u32::from_le_bytes(word))
+        };
+        let n = read_u32()? as usize;
+        let hash = match read_u32()? {
+            0 => HashKind::Polynomial,
+            1 => HashKind::Zobrist,
+            _ => return Err(invalid("unknown hash in endgame table")),
+        };
+        let depth = read_u32()?;
+        let mut long = [0; 8];
+        reader.read_exact(&mut long)?;
+        let Ok(len) = usize::try_from(u64::from_le_bytes(long)) else {
+            return Err(invalid("corrupt endgame table header"));
+        };
+        if n < 2 || depth > u8::MAX as u32 {
+            return Err(invalid("corrupt endgame table header"));
+        }
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        let Some(size) = len.checked_mul(9) else {
+            return Err(invalid("corrupt endgame table header"));
+        };
+        if bytes.len() != size {
+            return Err(invalid("endgame table has the wrong size"));
+        }
+        let (hashes, distances) = bytes.split_at(len * 8);
+        let distances = hashes.chunks_exact(8).map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap())).zip(distances.iter().map(|&d| d as u32)).collect();
+        Ok(Perimeter { n, hash, distances, depth })
+    }
+
+    /// Records every state within `self.depth - distance` moves of `state`, itself `distance`
+    /// moves from the solved board, where `last` was the last move; `expanded` holds the states
+    /// already expanded in this pass.
+    fn fill(&mut self, state: &mut State, expanded: &mut rustc_hash::FxHashSet<u64>, distance: u32, last: Move) {
+        match self.distances.get(&state.hash) {
+            Some(&known) if known < distance => return,
+            Some(&known) if known == distance && !expanded.insert(state.hash) => return,
+            _ => {
+                self.distances.insert(state.hash, distance);
+                expanded.insert(state.hash);
+            }
+        }
+        if distance == self.depth {
+            return;
+        }
+        for m in Move::ALL {
+            if last.is_clockwise() == m.is_clockwise() {
+                continue;
+            }
+            state.apply(m);
+            self.fill(state, expanded, distance + 1, m);
+            state.revert(m);
+        }
+    }
+}
+
+/// Shared perimeters by board size and hash.
+type Perimeters = HashMap<(usize, HashKind), Arc<Perimeter>>;
+
+/// States of the `Perimeter` of `Perimeter::get`.
+const PERIMETER_LIMIT: usize = 1 << 22;
+/// Nodes the forward half of the endgame may expand.
+const FORWARD_LIMIT: u64 = 1 << 20;
+
+impl Perimeter {
+    /// The perimeter of up to `PERIMETER_LIMIT` states of the board of size `n`, built on first
+    /// use and shared. With `deadline`, the build stops before it (see `build_until`), and a
+    /// perimeter cut short is only returned, not shared.
+    pub fn get(n: usize, hash: HashKind, deadline: Option<Instant>) -> Arc<Perimeter> {
+        static CACHE: OnceLock<Mutex<Perimeters>> = OnceLock::new();
+        let cache = CACHE.get_or_init(Default::default);
+        if let Some(perimeter) = cache.lock().unwrap().get(&(n, hash)) {
+            return perimeter.clone();
+        }
+        // built without the lock, which would hold up every other search needing a perimeter
+        let perimeter = Perimeter::build_until(&Input::solved(n), hash, PERIMETER_LIMIT, deadline);
+        let complete = perimeter.len() * 5 >= PERIMETER_LIMIT;
+        let perimeter = Arc::new(perimeter);
+        if !complete {
+            return perimeter;
+        }
+        cache.lock().unwrap().entry((n, hash)).or_insert(perimeter).clone()
+    }
+}
+
+/// Forward half of the endgame: the states `depth` moves from one start, looked up in the
+/// perimeter.
+struct Forward<'a> {
+    perimeter: &'a Perimeter,
+    path: Vec<Move>,
+    /// distance left from the meeting state and the path to it, for the closest one so far
+    best: Option<(u32, Vec<Move>)>,
+    nodes: u64,
+    deadline: Option<Instant>,
+    /// the deadline passed
+    stopped: bool,
+}
+
+impl Forward<'_> {
+    fn dfs(&mut self, state: &mut State, depth: u32, last: Move) {
+        if depth == 0 {
+            if let Some(distance) = self.perimeter.distance(state) {
+                if self.best.as_ref().is_none_or(|(best, _)| distance < *best) {
+                    self.best = Some((distance, self.path.clone()));
+                }
+            }
+            return;
+        }
+        if self.nodes >= FORWARD_LIMIT || self.stopped {
+            return;
+        }
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(1 << 10) && self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            self.stopped = true;
+        }
+        for m in Move::ALL {
+            // moves alternate, and undoing the last move only comes back to a shallower state
+            if last.is_clockwise() == m.is_clockwise() || last.inverse() == m {
+                continue;
+            }
+            state.apply(m);
+            self.path.push(m);
+            self.dfs(state, depth - 1, m);
+            self.path.pop();
+            state.revert(m);
+        }
+    }
+}
+
+/// A shortest finishing sequence from one of `starts`, each a state with its last move: they
+/// search forward one more move at a time until one of them meets `perimeter`, which is then
+/// followed back. Returns the index of that start and the sequence, or `None` if they do not meet
+/// within `FORWARD_LIMIT` nodes or before `deadline`.
+pub fn meet(perimeter: &Perimeter, starts: &mut [(State, Move)], config: &SolverConfig, deadline: Option<Instant>) -> Option<(usize, Vec<Move>)> {
+    let start = Instant::now();
+    let mut forward = Forward { perimeter, path: vec![], best: None, nodes: 0, deadline, stopped: false };
+    let mut depth = 0;
+    let found = loop {
+        // the first depth with a meeting holds the shortest sequence, see `Perimeter`
+        let mut found: Option<(u32, usize, Vec<Move>)> = None;
+        for (k, (state, last)) in starts.iter_mut().enumerate() {
+            forward.best = None;
+            forward.dfs(state, depth, *last);
+            if let Some((distance, path)) = forward.best.take() {
+                if found.as_ref().is_none_or(|(best, ..)| distance < *best) {
+                    found = Some((distance, k, path));
+                }
+            }
+        }
+        if found.is_some() || forward.nodes >= FORWARD_LIMIT || forward.stopped {
+            break found;
+        }
+        depth += 1;
+    };
+
+    let tail = found.and_then(|(_, k, path)| {
+        let (mut state, last) = starts[k].clone();
+        for &m in &path {
+            state.apply(m);
+        }
+        let rest = perimeter.path_to_goal(&state, path.last().copied().unwrap_or(last))?;
+        Some((k, path.into_iter().chain(rest).collect::<Vec<_>>()))
+    });
+    if config.verbose >= 1 {
+        match &tail {
+            Some((k, tail)) => eprintln!(
+                "endgame: {} moves from state {} of {} ({} within {} of the goal), {} nodes, {:.2}s",
+                tail.len(),
+                k + 1,
+                starts.len(),
+                perimeter.len(),
+                perimeter.depth(),
+                forward.nodes,
+                start.elapsed().as_secs_f64()
+            ),
+            None => eprintln!("endgame: no meeting within {} forward moves, {:.2}s; the beam search goes on", depth, start.elapsed().as_secs_f64()),
+        }
+    }
+    tail
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::random_moves;
+    use crate::{solve, SolverConfig};
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("endgame-{}-{}.bin", std::process::id(), name))
+    }
+
+    #[test]
+    fn saved_table_loads_back() {
+        let path = temp_path("round-trip");
+        for hash in [HashKind::Polynomial, HashKind::Zobrist] {
+            let table = Perimeter::build_to_depth(3, hash, 5);
+            table.save(&path).unwrap();
+            let loaded = Perimeter::load(&path).unwrap();
+            assert!(loaded.n == table.n && loaded.hash == table.hash && loaded.depth == table.depth, "{:?}: header changed", hash);
+            assert!(loaded.distances == table.distances, "{:?}: distances changed", hash);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn overflowing_table_is_rejected() {
+        let path = temp_path("overflow");
+        let mut bytes = MAGIC.to_vec();
+        for word in [3u32, 0, 5] {
+            bytes.extend(word.to_le_bytes());
+        }
+        // 9 bytes per entry overflow
+        bytes.extend((u64::MAX / 8).to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+        let error = Perimeter::load(&path).err().expect("loaded a table whose size overflows");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn table_of_another_board_is_ignored() {
+        let table = Arc::new(Perimeter::build_to_depth(3, HashKind::Polynomial, 6));
+        assert!(table.fits(3, HashKind::Polynomial));
+        assert!(!table.fits(4, HashKind::Polynomial) && !table.fits(3, HashKind::Zobrist));
+        // its distances would end the search on the wrong boards
+        for (n, hash) in [(4, HashKind::Polynomial), (3, HashKind::Zobrist)] {
+            let input = Input::scrambled(n, &random_moves(40, n as u64));
+            let config = SolverConfig { endgame_table: Some(table.clone()), hash, ..SolverConfig::default() };
+            let solution = solve(&input, &config);
+            let mut state = State::from_input(&input);
+            solution.moves.iter().for_each(|&m| state.apply(m));
+            assert!(solution.solved && state.is_solved(), "n = {}, {:?}: not solved", n, hash);
+        }
+    }
+}
@@ -0,0 +1,356 @@
+use crate::geometry::Geometry;
+use crate::linear::{feature_delta, features, Features, LinearModel, FEATURES};
+use crate::moves::Move;
+use crate::pattern::PatternDatabase;
+use crate::state::{BlankTarget, State};
+use std::sync::Arc;
+
+/// Scores states for the beam search; lower is better and 0 means solved.
+pub trait Evaluator: Send + Sync {
+    fn evaluate(&self, state: &State) -> i64;
+
+    /// `evaluate` after applying `m` minus `evaluate` now. Called for every candidate, so it
+    /// should only look at what `m` changes.
+    fn delta(&self, state: &State, m: Move) -> i64;
+
+    /// Switches to new term weights; `weights.distance_exponent` is set on the state by the
+    /// caller. Evaluators without such weights ignore it.
+    fn set_weights(&mut self, _weights: &Weights) {}
+}
+
+/// Weights of the terms of `DefaultEvaluator`, which a `Schedule` can change during a search.
+///
+/// [`Schedule`]: crate::schedule::Schedule
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Weights {
+    pub distance_exponent: f64,
+    pub mismatch_weight: f64,
+    pub blank_weight: f64,
+}
+
+/// Sum of `State::weighted_distance` over every tile plus `mismatch_weight` times
+/// `State::mismatch_cost` plus `blank_weight` times the distance of the blank from its target.
+/// Inactive tiles (see `State::set_active`) count `inactive_weight` times their distance.
+///
+/// `approach_weight` adds a term for the moves the blank needs before it touches the tile of
+/// `State::blank_gap`, since a tile only moves next to the blank.
+pub struct DefaultEvaluator {
+    pub mismatch_weight: f64,
+    pub blank_weight: f64,
+    pub inactive_weight: f64,
+    pub approach_weight: f64,
+    pub approach: BlankTarget,
+}
+
+impl Default for DefaultEvaluator {
+    fn default() -> Self {
+        DefaultEvaluator { mismatch_weight: 1.0, blank_weight: 0.0, inactive_weight: 0.0, approach_weight: 0.0, approach: BlankTarget::Nearest }
+    }
+}
+
+impl DefaultEvaluator {
+    fn tile_score(&self, state: &State, i: usize, cell: (usize, usize)) -> i64 {
+        if state.is_active(i) {
+            state.weighted_distance_at(i, cell) as i64
+        } else if self.inactive_weight == 0.0 {
+            0
+        } else {
+            (self.inactive_weight * state.unmasked_weighted_distance_at(i, cell) as f64).round() as i64
+        }
+    }
+
+    fn mismatch_score(&self, mismatch_cost: u32) -> i64 {
+        (self.mismatch_weight * mismatch_cost as f64).round() as i64
+    }
+
+    /// Weighted distance of the blank from its target if it were at `cell`; 0 unless the blank
+    /// is active, see `State::set_active`.
+    fn blank_score(&self, state: &State, cell: (usize, usize)) -> i64 {
+        if !state.is_active(0) {
+            return 0;
+        }
+        (self.blank_weight * state.geometry.distance(cell, state.target_positions[0]) as f64).round() as i64
+    }
+
+    /// `approach_weight` times the steps of the blank to a cell next to a tile `gap` away.
+    fn approach_score(&self, gap: u32) -> i64 {
+        (self.approach_weight * gap.saturating_sub(1) as f64).round() as i64
+    }
+}
+
+impl Evaluator for DefaultEvaluator {
+    fn evaluate(&self, state: &State) -> i64 {
+        let mut score = 0;
+        for (i, &cell) in state.tile_positions.iter().enumerate() {
+            score += self.tile_score(state, i, cell);
+        }
+        score += self.mismatch_score(state.mismatch_cost()) + self.blank_score(state, state.zero_position);
+        if self.approach_weight != 0.0 {
+            score += self.approach_score(state.blank_gap(self.approach));
+        }
+        score
+    }
+
+    fn delta(&self, state: &State, m: Move) -> i64 {
+        let a = state.zero_position;
+        let (b, c) = state.geometry.rotated_cells(a, m);
+        let x = state.board[b.0][b.1] as usize;
+        let y = state.board[c.0][c.1] as usize;
+        let mut diff = 0;
+        diff += self.tile_score(state, x, c) - self.tile_score(state, x, b);
+        diff += self.tile_score(state, y, a) - self.tile_score(state, y, c);
+        diff += self.mismatch_score(state.mismatch_cost_after(m)) - self.mismatch_score(state.mismatch_cost());
+        diff += self.blank_score(state, b) - self.blank_score(state, a);
+        if self.approach_weight != 0.0 {
+            diff += self.approach_score(state.blank_gap_after(self.approach, m)) - self.approach_score(state.blank_gap(self.approach));
+        }
+        diff
+    }
+
+    fn set_weights(&mut self, weights: &Weights) {
+        self.mismatch_weight = weights.mismatch_weight;
+        self.blank_weight = weights.blank_weight;
+    }
+}
+
+/// `DefaultEvaluator` plus `pattern_weight` times the values of the `PatternDatabase` of each
+/// group of tiles.
+///
+/// Additive databases (see `PatternDatabase::build_additive`) add their summed values, the steps
+/// the tiles of all groups need at least, half of which bound the moves left. Other databases
+/// add the moves each group needs beyond the torus distances of its tiles: the cost of bringing
+/// the blank to them and of their getting in each other's way. The databases are all of one kind.
+///
+/// Tiles are grouped greedily in tile order with the largest database whose shape fits their
+/// targets; a database of shape `[(0, 0)]` covers the remaining tiles and is built on the fly if
+/// none is given.
+///
+/// A group counts its value with the blank wherever suits it best (`get_any_blank`): counted in
+/// every group, the walk of the blank would be charged once per unsolved group, and every move
+/// would change every group. The blank is left to the terms of the base evaluator, so `delta`
+/// only looks up the groups of the two tiles a move rotates.
+pub struct PatternEvaluator {
+    base: DefaultEvaluator,
+    pattern_weight: f64,
+    additive: bool,
+    databases: Vec<Arc<PatternDatabase>>,
+    groups: Vec<Group>,
+    /// tile -> index of its group in `groups`
+    group_of: Vec<usize>,
+}
+
+struct Group {
+    database: usize,
+    tiles: Vec<usize>,
+    /// cell id -> cell id relative to the center, translating the first target onto it
+    relative: Vec<usize>,
+}
+
+impl PatternEvaluator {
+    pub fn new(state: &State, mut databases: Vec<Arc<PatternDatabase>>, base: DefaultEvaluator, pattern_weight: f64) -> Self {
+        let n = state.n();
+        assert!(databases.iter().all(|database| database.n() == n), "pattern database built for another size");
+        assert!(databases.iter().all(|database| database.shape().len() <= 4), "pattern groups are limited to 4 tiles");
+        let additive = databases.first().is_some_and(|database| database.is_additive());
+        assert!(databases.iter().all(|database| database.is_additive() == additive), "additive and other pattern databases mixed");
+        if !databases.iter().any(|database| database.shape().len() == 1) {
+            let single = if additive { PatternDatabase::build_additive(n, &[(0, 0)]) } else { PatternDatabase::build(n, &[(0, 0)]) };
+            databases.push(Arc::new(single));
+        }
+        databases.sort_by_key(|database| std::cmp::Reverse(database.shape().len()));
+
+        let geometry = &state.geometry;
+        let size = 2 * n - 1;
+        let mut tile_at = vec![0; size * size];
+        for (tile, &(i, j)) in state.target_positions.iter().enumerate() {
+            tile_at[i * size + j] = tile;
+        }
+        let mut assigned = vec![false; state.target_positions.len()];
+        let mut groups = vec![];
+        for tile in 1..state.target_positions.len() {
+            if assigned[tile] {
+                continue;
+            }
+            let target = state.target_positions[tile];
+            for (idx, database) in databases.iter().enumerate() {
+                let tiles = database.shape().iter().map(|&offset| {
+                    let (i, j) = geometry.translate(target, offset);
+                    tile_at[i * size + j]
+                });
+                let tiles = tiles.collect::<Vec<_>>();
+                if tiles.iter().any(|&t| t == 0 || assigned[t]) {
+                    continue;
+                }
+                let shift = (n as i32 - 1 - target.0 as i32, n as i32 - 1 - target.1 as i32);
+                let relative = geometry.cells().iter().map(|&cell| geometry.cell_id(geometry.translate(cell, shift))).collect();
+                tiles.iter().for_each(|&t| assigned[t] = true);
+                groups.push(Group { database: idx, tiles, relative });
+                break;
+            }
+        }
+
+        let mut group_of = vec![usize::MAX; state.target_positions.len()];
+        for (idx, group) in groups.iter().enumerate() {
+            group.tiles.iter().for_each(|&tile| group_of[tile] = idx);
+        }
+        PatternEvaluator { base, pattern_weight, additive, databases, groups, group_of }
+    }
+
+    /// Database value of `group`, minus the distances of its tiles unless the database is
+    /// additive, with each tile at `position(tile)`.
+    fn group_value(&self, state: &State, group: &Group, position: impl Fn(usize) -> (usize, usize)) -> u32 {
+        let mut tiles = [0; 4];
+        let mut distance = 0;
+        for (slot, &tile) in tiles.iter_mut().zip(group.tiles.iter()) {
+            let cell = position(tile);
+            *slot = group.relative[state.geometry.cell_id(cell)];
+            distance += state.geometry.distance(cell, state.target_positions[tile]);
+        }
+        let value = self.databases[group.database].get_any_blank(&tiles[..group.tiles.len()]);
+        if self.additive {
+            value as u32
+        } else {
+            (value as u32).saturating_sub(distance)
+        }
+    }
+
+    /// Score of `group`, rounded per group so that `delta` adds up to `evaluate`.
+    fn group_score(&self, state: &State, group: &Group, position: impl Fn(usize) -> (usize, usize)) -> i64 {
+        (self.pattern_weight * self.group_value(state, group, position) as f64).round() as i64
+    }
+}
+
+impl Evaluator for PatternEvaluator {
+    fn evaluate(&self, state: &State) -> i64 {
+        let pattern: i64 = self.groups.iter().map(|group| self.group_score(state, group, |tile| state.tile_positions[tile])).sum();
+        self.base.evaluate(state) + pattern
+    }
+
+    fn delta(&self, state: &State, m: Move) -> i64 {
+        let a = state.zero_position;
+        let (b, c) = state.geometry.rotated_cells(a, m);
+        let x = state.board[b.0][b.1] as usize;
+        let y = state.board[c.0][c.1] as usize;
+        let moved = |tile: usize| if tile == x { c } else if tile == y { a } else { state.tile_positions[tile] };
+        let change = |idx: usize| {
+            let group = &self.groups[idx];
+            self.group_score(state, group, moved) - self.group_score(state, group, |tile| state.tile_positions[tile])
+        };
+        let (gx, gy) = (self.group_of[x], self.group_of[y]);
+        let pattern = if gx == gy { change(gx) } else { change(gx) + change(gy) };
+        self.base.delta(state, m) + pattern
+    }
+
+    fn set_weights(&mut self, weights: &Weights) {
+        self.base.set_weights(weights);
+    }
+}
+
+/// `LinearModel` prediction of the remaining moves times `scale`, a power of two that brings the
+/// largest weight near 2^20.
+///
+/// Weights are rounded to integers once, so `delta` is exact, and keep their sign, so the order
+/// of the states is that of the model up to rounding.
+pub struct LinearEvaluator {
+    weights: [i64; FEATURES],
+    scale: f64,
+    /// `Geometry::weighted_distances` at the exponent of the model
+    weighted: Vec<u32>,
+}
+
+impl LinearEvaluator {
+    pub fn new(model: &LinearModel, geometry: &Geometry) -> Self {
+        let largest = model.weights.iter().fold(0.0f64, |m, w| m.max(w.abs()));
+        let scale = if largest > 0.0 { 2f64.powi(20 - largest.log2().ceil() as i32) } else { 1.0 };
+        let weights = model.weights.map(|w| (w * scale).round() as i64);
+        LinearEvaluator { weights, scale, weighted: geometry.weighted_distances(model.distance_exponent) }
+    }
+
+    /// Factor between `evaluate` and `LinearModel::predict`.
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    fn dot(&self, f: &Features) -> i64 {
+        self.weights.iter().zip(f.iter()).map(|(&w, &x)| w * x).sum()
+    }
+}
+
+impl Evaluator for LinearEvaluator {
+    fn evaluate(&self, state: &State) -> i64 {
+        self.dot(&features(state, &self.weighted))
+    }
+
+    fn delta(&self, state: &State, m: Move) -> i64 {
+        self.dot(&feature_delta(state, m, &self.weighted))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Input;
+    use crate::utils::random_moves;
+
+    /// Every evaluator the search can use, with every term switched on.
+    fn evaluators(state: &State) -> Vec<Box<dyn Evaluator>> {
+        let base = |approach| DefaultEvaluator { mismatch_weight: 1.3, blank_weight: 0.7, inactive_weight: 0.4, approach_weight: 0.6, approach };
+        let pair = Arc::new(PatternDatabase::build(state.n(), &[(0, 0), (0, 1)]));
+        let additive_pair = Arc::new(PatternDatabase::build_additive(state.n(), &[(0, 0), (0, 1)]));
+        let model = LinearModel { weights: std::array::from_fn(|k| k as f64 - 6.5), distance_exponent: 1.3 };
+        vec![
+            Box::new(base(BlankTarget::Nearest)),
+            Box::new(base(BlankTarget::Farthest)),
+            Box::new(PatternEvaluator::new(state, vec![pair], base(BlankTarget::Nearest), 0.8)),
+            Box::new(PatternEvaluator::new(state, vec![additive_pair], base(BlankTarget::Nearest), 0.8)),
+            Box::new(LinearEvaluator::new(&model, &state.geometry)),
+        ]
+    }
+
+    #[test]
+    fn delta_matches_evaluate() {
+        for n in [3, 5] {
+            let solved = State::from_input(&Input::solved(n));
+            // every third tile inactive, blank included
+            let masks = [None, Some((0..solved.tile_positions.len()).map(|i| i % 3 != 0).collect::<Vec<_>>())];
+            for (mask, exponent) in masks.iter().flat_map(|mask| [(mask, 1.7), (mask, 2.4)]) {
+                let mut state = State::from_input(&Input::scrambled(n, &random_moves(50, n as u64)));
+                if let Some(mask) = mask {
+                    state.set_active(mask.clone());
+                }
+                state.set_distance_exponent(exponent);
+                for (idx, evaluator) in evaluators(&state).iter().enumerate() {
+                    let mut state = state.clone();
+                    for walk in random_moves(100, idx as u64) {
+                        for m in Move::ALL {
+                            let before = evaluator.evaluate(&state);
+                            state.apply(m);
+                            let after = evaluator.evaluate(&state);
+                            state.revert(m);
+                            assert_eq!(evaluator.delta(&state, m), after - before, "evaluator {}, n = {}, exponent {}, {:?}", idx, n, exponent, m);
+                        }
+                        state.apply(walk);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn linear_evaluator_matches_the_model() {
+        for (n, seed) in [(3, 1), (5, 2), (8, 3)] {
+            // weights of both signs and very different sizes
+            let model = LinearModel { weights: std::array::from_fn(|k| (k as f64 - 7.3) * [1.0, 0.01, 30.0][k % 3]), distance_exponent: 1.5 };
+            let mut state = State::from_input(&Input::solved(n));
+            let evaluator = LinearEvaluator::new(&model, &state.geometry);
+            let weighted = state.geometry.weighted_distances(model.distance_exponent);
+            for m in random_moves(200, seed) {
+                state.apply(m);
+                let f = features(&state, &weighted);
+                let rounding = 0.5 * f.iter().map(|x| x.abs() as f64).sum::<f64>() / evaluator.scale();
+                let (got, expected) = (evaluator.evaluate(&state) as f64 / evaluator.scale(), model.predict(&f));
+                assert!((got - expected).abs() <= rounding, "n = {}: {} instead of {}", n, got, expected);
+            }
+        }
+    }
+}
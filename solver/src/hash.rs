@@ -0,0 +1,143 @@
+use crate::utils::{bases, change_by, mix, mul, BASE};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Incremental hash of a `State`, combining one term per slot: a slot is a tile at a cell or the
+/// direction of the last move, and holds a `key` and a `value`. Value 0 contributes nothing, so a
+/// hash is built by changing every slot from 0.
+///
+/// [`State`]: crate::state::State
+pub trait StateHash: Send + Sync {
+    /// `hash` with one slot of `key` changed from `old` to `new`.
+    fn change(&self, hash: u64, key: usize, old: u64, new: u64) -> u64;
+}
+
+/// Sum of `value * BASE^k` mod 2^61-1, with a power `k` per key.
+pub struct PolynomialHash {
+    bases: Vec<u64>,
+}
+
+impl PolynomialHash {
+    pub fn new(keys: usize) -> Self {
+        let mut bases = bases(BASE, keys - 1);
+        // the last key has the power just above all the others
+        bases.push(mul(bases[0], BASE));
+        PolynomialHash { bases }
+    }
+}
+
+impl StateHash for PolynomialHash {
+    fn change(&self, hash: u64, key: usize, old: u64, new: u64) -> u64 {
+        change_by(hash, self.bases[key], old, new)
+    }
+}
+
+/// XOR of a pseudo-random word per key and value, Zobrist style. The words are drawn by mixing
+/// the value with a random salt per key rather than stored, since values range over 64 bits.
+pub struct ZobristHash {
+    salts: Vec<u64>,
+}
+
+impl ZobristHash {
+    pub fn new(keys: usize, seed: u64) -> Self {
+        ZobristHash { salts: (0..keys as u64).map(|key| mix(mix(seed) ^ key)).collect() }
+    }
+
+    fn word(&self, key: usize, value: u64) -> u64 {
+        if value == 0 {
+            0
+        } else {
+            mix(self.salts[key] ^ value)
+        }
+    }
+}
+
+impl StateHash for ZobristHash {
+    fn change(&self, hash: u64, key: usize, old: u64, new: u64) -> u64 {
+        hash ^ self.word(key, old) ^ self.word(key, new)
+    }
+}
+
+/// Which `StateHash` a search uses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HashKind {
+    Polynomial,
+    Zobrist,
+}
+
+impl HashKind {
+    pub fn build(self, keys: usize) -> Arc<dyn StateHash> {
+        match self {
+            HashKind::Polynomial => Arc::new(PolynomialHash::new(keys)),
+            HashKind::Zobrist => Arc::new(ZobristHash::new(keys, 0)),
+        }
+    }
+}
+
+impl FromStr for HashKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "polynomial" => Ok(HashKind::Polynomial),
+            "zobrist" => Ok(HashKind::Zobrist),
+            _ => Err(format!("unknown hash {:?} (expected polynomial or zobrist)", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Input;
+    use crate::moves::Move;
+    use crate::state::State;
+    use crate::utils::{mix, random_moves, MOD};
+
+    const KINDS: [HashKind; 2] = [HashKind::Polynomial, HashKind::Zobrist];
+
+    #[test]
+    fn change_matches_recomputation() {
+        for kind in KINDS {
+            let hasher = kind.build(50);
+            let from_scratch = |values: &[u64]| values.iter().enumerate().fold(0, |hash, (key, &value)| hasher.change(hash, key, 0, value));
+            let mut values: Vec<u64> = (0..50).map(|key| mix(key) % MOD).collect();
+            let mut hash = from_scratch(&values);
+            for step in 0..1000 {
+                let key = mix(step) as usize % values.len();
+                let value = if step % 7 == 0 { 0 } else { mix(step + 1000) % MOD };
+                hash = hasher.change(hash, key, values[key], value);
+                values[key] = value;
+                assert_eq!(hash, from_scratch(&values), "{:?}: step {}", kind, step);
+            }
+        }
+    }
+
+    /// `State::hash` of a state with the board of `state` built from scratch, whose last move is
+    /// `last`.
+    fn recomputed(state: &State, kind: HashKind, last: Move) -> u64 {
+        let mut fresh = State::from_input(&Input { n: state.n(), board: state.board.clone() });
+        fresh.set_hash(kind);
+        fresh.apply(last.inverse());
+        fresh.apply(last);
+        fresh.hash
+    }
+
+    #[test]
+    fn state_hash_is_incremental() {
+        for kind in KINDS {
+            let mut state = State::from_input(&Input::scrambled(4, &random_moves(100, 4)));
+            state.set_hash(kind);
+            let start = state.hash;
+            let moves = random_moves(300, 40);
+            for &m in moves.iter() {
+                state.apply(m);
+                assert_eq!(state.hash, recomputed(&state, kind, m), "{:?}: after {:?}", kind, m);
+            }
+            for &m in moves.iter().rev() {
+                state.revert(m);
+            }
+            assert_eq!(state.hash, start, "{:?}: not restored by reverting", kind);
+        }
+    }
+}
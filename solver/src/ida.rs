@@ -0,0 +1,343 @@
+use crate::endgame::Perimeter;
+use crate::moves::Move;
+use crate::pattern::PatternDatabase;
+use crate::state::State;
+use crate::{solve, Input, Solution, SolverConfig};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+/// Admissible estimate of the remaining moves.
+///
+/// States of the `Perimeter` have their exact distance. Any other state is at least one move
+/// beyond it, and at least the larger of the distance of the blank and half the summed additive
+/// `PatternDatabase` values of disjoint groups of tiles (see `PatternDatabase::build_additive`),
+/// which bounds `bound::lower_bound`. The start of the search has no last move, so it is looked
+/// up under both directions, see `estimate_start`.
+///
+/// Groups count their value with the blank wherever suits them best (`get_any_blank`), so a move
+/// only changes the groups of the two tiles it rotates, and `Ida` keeps the values up to date
+/// with `values_after` instead of looking every group up at every node. Under a time limit,
+/// tables are only built up to `TIMED_TABLE_LEN` entries; without them, each tile is a group of
+/// its own, worth its torus distance.
+///
+/// [`bound::lower_bound`]: crate::bound::lower_bound
+struct Heuristic {
+    perimeter: Arc<Perimeter>,
+    groups: Arc<Groups>,
+    /// tile -> index of its group in `groups`
+    group_of: Vec<usize>,
+    /// cell id -> cell id relative to the center, per group with a table, see
+    /// `PatternEvaluator`
+    ///
+    /// [`PatternEvaluator`]: crate::evaluator::PatternEvaluator
+    relative: Vec<Vec<usize>>,
+}
+
+/// Entries an additive table may have; groups are as large as this allows, up to 4 tiles.
+const GROUP_TABLE_LEN: usize = 1 << 22;
+/// Entries a table may have under a time limit, which builds in a few milliseconds; the one-tile
+/// table of n = 20 has over a million and takes a third of a second.
+const TIMED_TABLE_LEN: usize = 1 << 16;
+
+impl Heuristic {
+    fn new(state: &State, input: &Input, config: &SolverConfig, deadline: Option<Instant>) -> Self {
+        let n = state.n();
+        let geometry = &state.geometry;
+        let table_len = if deadline.is_some() { TIMED_TABLE_LEN } else { GROUP_TABLE_LEN };
+        let groups = additive_groups(state, table_len);
+        let mut group_of = vec![usize::MAX; state.tile_positions.len()];
+        for (idx, (group, _)) in groups.iter().enumerate() {
+            group.iter().for_each(|&tile| group_of[tile] = idx);
+        }
+        let relative: Vec<Vec<usize>> = groups
+            .iter()
+            .map(|(group, database)| match database {
+                Some(_) => {
+                    let target = state.target_positions[group[0]];
+                    let shift = (n as i32 - 1 - target.0 as i32, n as i32 - 1 - target.1 as i32);
+                    geometry.cells().iter().map(|&cell| geometry.cell_id(geometry.translate(cell, shift))).collect()
+                }
+                None => vec![],
+            })
+            .collect();
+
+        let perimeter = Perimeter::get(input.n, config.hash, deadline);
+        if config.verbose >= 1 {
+            eprintln!("perimeter: {} states within {} moves of the goal", perimeter.len(), perimeter.depth());
+        }
+        Heuristic { perimeter, groups, group_of, relative }
+    }
+
+    /// Estimate of `state`, whose summed group values are `steps`.
+    fn estimate(&self, state: &State, steps: u32) -> u32 {
+        let bound = self.lower_bound(state, steps);
+        // a state of the perimeter is no further than its depth, so beyond it, it is not looked up
+        if bound <= self.perimeter.depth() {
+            if let Some(distance) = self.perimeter.distance(state) {
+                return distance;
+            }
+        }
+        (self.perimeter.depth() + 1).max(bound)
+    }
+
+    /// `estimate` of a state with no last move: the closer of the same board reached by a move of
+    /// either direction, see `Perimeter::deepen`; out of the perimeter, the lower bound alone.
+    fn estimate_start(&self, state: &State, steps: u32) -> u32 {
+        let distance = [Move::Cw1, Move::CcwA]
+            .into_iter()
+            .filter_map(|m| {
+                let mut state = state.clone();
+                state.apply(m);
+                state.apply(m.inverse());
+                self.perimeter.distance(&state)
+            })
+            .min();
+        distance.unwrap_or_else(|| self.lower_bound(state, steps))
+    }
+
+    fn lower_bound(&self, state: &State, steps: u32) -> u32 {
+        steps.div_ceil(2).max(state.raw_distance(0))
+    }
+
+    /// Value of every group.
+    fn values(&self, state: &State) -> Vec<u32> {
+        (0..self.groups.len()).map(|idx| self.value(state, idx, |tile| state.tile_positions[tile])).collect()
+    }
+
+    /// Values of the groups of the two tiles `m` rotates, as `(group, value)`, once `m` is
+    /// applied; the second is the first again if both tiles are in one group.
+    fn values_after(&self, state: &State, m: Move) -> [(usize, u32); 2] {
+        let a = state.zero_position;
+        let (b, c) = state.geometry.rotated_cells(a, m);
+        let x = state.board[b.0][b.1] as usize;
+        let y = state.board[c.0][c.1] as usize;
+        let moved = |tile: usize| if tile == x { c } else if tile == y { a } else { state.tile_positions[tile] };
+        let (gx, gy) = (self.group_of[x], self.group_of[y]);
+        let vx = self.value(state, gx, moved);
+        let vy = if gy == gx { vx } else { self.value(state, gy, moved) };
+        [(gx, vx), (gy, vy)]
+    }
+
+    /// Value of the group `idx` with each of its tiles at `position(tile)`.
+    fn value(&self, state: &State, idx: usize, position: impl Fn(usize) -> (usize, usize)) -> u32 {
+        let (group, database) = &self.groups[idx];
+        let Some(database) = database else {
+            return group.iter().map(|&tile| state.geometry.distance(position(tile), state.target_positions[tile])).sum();
+        };
+        let relative = &self.relative[idx];
+        let mut cells = [0; 4];
+        for (cell, &tile) in cells.iter_mut().zip(group.iter()) {
+            *cell = relative[state.geometry.cell_id(position(tile))];
+        }
+        database.get_any_blank(&cells[..group.len()]) as u32
+    }
+}
+
+/// Tiles of each group, with the additive table of its shape, if any.
+type Groups = Vec<(Vec<usize>, Option<Arc<PatternDatabase>>)>;
+
+/// Shared groups by board size and group size.
+type SharedGroups = HashMap<(usize, usize), Arc<Groups>>;
+
+/// Runs of consecutive tiles, whose targets are close, and their additive tables, built on first
+/// use for each board size and group size and shared. Groups of the same shape share a table.
+/// Groups are as large as tables of up to `table_len` entries allow; if even a single tile needs
+/// a larger one, every tile is a group of its own without a table.
+fn additive_groups(state: &State, table_len: usize) -> Arc<Groups> {
+    static CACHE: OnceLock<Mutex<SharedGroups>> = OnceLock::new();
+    let n = state.n();
+    let tiles: Vec<usize> = (1..state.tile_positions.len()).collect();
+    let Some(size) = (1..=4).rev().find(|&k| PatternDatabase::table_len(n, k).is_some_and(|len| len <= table_len)) else {
+        return Arc::new(tiles.iter().map(|&tile| (vec![tile], None)).collect());
+    };
+    let cache = CACHE.get_or_init(Default::default);
+    if let Some(groups) = cache.lock().unwrap().get(&(n, size)) {
+        return groups.clone();
+    }
+    let mut groups: Vec<(Vec<usize>, Arc<PatternDatabase>)> = vec![];
+    for group in tiles.chunks(size) {
+        let first = state.target_positions[group[0]];
+        let shape: Vec<(i32, i32)> = group
+            .iter()
+            .map(|&tile| {
+                let target = state.target_positions[tile];
+                (target.0 as i32 - first.0 as i32, target.1 as i32 - first.1 as i32)
+            })
+            .collect();
+        let database = match groups.iter().find(|(_, database)| database.shape() == shape) {
+            Some((_, database)) => database.clone(),
+            None => Arc::new(PatternDatabase::build_additive(n, &shape)),
+        };
+        groups.push((group.to_vec(), database));
+    }
+    let groups = groups.into_iter().map(|(group, database)| (group, Some(database))).collect();
+    cache.lock().unwrap().entry((n, size)).or_insert(Arc::new(groups)).clone()
+}
+
+/// Why `Ida::dfs` returned.
+enum Outcome {
+    Found,
+    /// smallest estimated length above the threshold, `u32::MAX` if every branch was cut off as
+    /// already expanded
+    Exceeded(u32),
+    Stopped,
+}
+
+struct Ida<'a> {
+    state: State,
+    heuristic: Heuristic,
+    /// smallest depth at which each state was expanded in the current iteration
+    visited: rustc_hash::FxHashMap<u64, u32>,
+    /// `Heuristic::values` of `state`
+    values: Vec<u32>,
+    /// their sum
+    steps: u32,
+    path: Vec<Move>,
+    nodes: u64,
+    config: &'a SolverConfig,
+    start: Instant,
+}
+
+/// Memory the transposition table may take; once growing would take more, states are no longer
+/// recorded.
+const TABLE_BYTES: usize = 1 << 30;
+
+impl Ida<'_> {
+    /// The table doubles its buckets when full, each an entry and a control byte.
+    fn table_has_room(&self) -> bool {
+        let capacity = self.visited.capacity();
+        self.visited.len() < capacity || 2 * capacity.max(1) * (std::mem::size_of::<(u64, u32)>() + 1) <= TABLE_BYTES
+    }
+
+    /// Sets the values of groups, keeping `steps` their sum; a group given twice gets the same
+    /// value both times.
+    fn set_values(&mut self, values: [(usize, u32); 2]) {
+        for (idx, value) in values {
+            self.steps = self.steps - self.values[idx] + value;
+            self.values[idx] = value;
+        }
+    }
+
+    fn out_of_budget(&self) -> bool {
+        if self.config.node_limit.is_some_and(|limit| self.nodes >= limit) {
+            return true;
+        }
+        self.nodes.is_multiple_of(1 << 8) && self.config.time_limit.is_some_and(|limit| self.start.elapsed() >= limit)
+    }
+
+    fn dfs(&mut self, depth: u32, threshold: u32, last: Option<Move>) -> Outcome {
+        let estimate = match last {
+            Some(_) => self.heuristic.estimate(&self.state, self.steps),
+            None => self.heuristic.estimate_start(&self.state, self.steps),
+        };
+        // the perimeter looks states up by hash, so a collision could give 0 on another board
+        if estimate == 0 && self.state.is_solved() {
+            return Outcome::Found;
+        }
+        if depth + estimate > threshold {
+            return Outcome::Exceeded(depth + estimate);
+        }
+        match self.visited.get(&self.state.hash) {
+            Some(&seen) if seen <= depth => return Outcome::Exceeded(u32::MAX),
+            _ if self.table_has_room() => {
+                self.visited.insert(self.state.hash, depth);
+            }
+            _ => {}
+        }
+        self.nodes += 1;
+        if self.out_of_budget() {
+            return Outcome::Stopped;
+        }
+
+        let mut next = u32::MAX;
+        for m in Move::ALL {
+            // moves alternate, and undoing the last move only comes back to a known state
+            if last.is_some_and(|last| last.is_clockwise() == m.is_clockwise() || last.inverse() == m) {
+                continue;
+            }
+            let after = self.heuristic.values_after(&self.state, m);
+            let before = after.map(|(idx, _)| (idx, self.values[idx]));
+            self.set_values(after);
+            self.state.apply(m);
+            self.path.push(m);
+            let outcome = self.dfs(depth + 1, threshold, Some(m));
+            if !matches!(outcome, Outcome::Found) {
+                self.path.pop();
+                self.state.revert(m);
+                self.set_values(before);
+            }
+            match outcome {
+                Outcome::Exceeded(bound) => next = next.min(bound),
+                outcome => return outcome,
+            }
+        }
+        Outcome::Exceeded(next)
+    }
+}
+
+/// Shortest solution by iterative deepening A* on `Heuristic`, pruning states already expanded at
+/// a smaller depth in the same iteration (by `State::hash`, so a collision could in principle
+/// cut off the optimum).
+///
+/// The beam search (`solve` without `optimal`, with half the time limit) first gives an upper
+/// bound: the iterations stop below its length, and once they have ruled out every shorter
+/// solution, its solution is proven optimal. At `node_limit` expanded nodes or at the time limit,
+/// the beam solution is returned, unproven. The nodes grow about sixfold per move of the
+/// threshold, so the default `node_limit` proves boards a little over 20 moves from solved; the
+/// full n = 3 instances, around 27 moves, are out of reach.
+pub fn solve_optimal(input: &Input, config: &SolverConfig) -> Solution {
+    search_optimal(input, config).0
+}
+
+/// `solve_optimal`, also returning the length no solution is shorter than.
+pub fn search_optimal(input: &Input, config: &SolverConfig) -> (Solution, u32) {
+    let start = Instant::now();
+    let mut state = State::from_input(input);
+    state.set_hash(config.hash);
+    if state.is_solved() {
+        return (Solution { moves: vec![], solved: true, score: 0, peak_nodes: 0, optimal: true }, 0);
+    }
+    let beam = SolverConfig { optimal: false, time_limit: config.time_limit.map(|limit| limit / 2), ..config.clone() };
+    let seed = solve(input, &beam);
+    // no solution of this length needs to be looked for
+    let upper = if seed.solved { seed.moves.len() as u32 } else { u32::MAX };
+    if config.verbose >= 1 {
+        eprintln!("beam search: {} moves, {:.2}s", seed.moves.len(), start.elapsed().as_secs_f64());
+    }
+
+    let heuristic = Heuristic::new(&state, input, config, config.time_limit.map(|limit| start + limit));
+    let values = heuristic.values(&state);
+    let steps = values.iter().sum();
+    let mut threshold = heuristic.estimate_start(&state, steps);
+    let mut ida = Ida { state, heuristic, visited: Default::default(), values, steps, path: vec![], nodes: 0, config, start };
+    while threshold < upper {
+        ida.visited.clear();
+        match ida.dfs(0, threshold, None) {
+            Outcome::Found => {
+                if config.verbose >= 1 {
+                    eprintln!("optimal: {} moves, {} nodes, {:.2}s", ida.path.len(), ida.nodes, start.elapsed().as_secs_f64());
+                }
+                let length = ida.path.len() as u32;
+                return (Solution { moves: ida.path, solved: true, score: 0, peak_nodes: 0, optimal: true }, length);
+            }
+            Outcome::Exceeded(next) => {
+                if config.verbose >= 1 {
+                    eprintln!("no solution in {} moves, {} nodes, {:.2}s", threshold, ida.nodes, start.elapsed().as_secs_f64());
+                }
+                // every branch cut off by the table says nothing of the next threshold
+                threshold = if next == u32::MAX { threshold + 1 } else { next };
+            }
+            Outcome::Stopped => {
+                if config.verbose >= 1 {
+                    eprintln!("stopped after {} nodes, {:.2}s: no solution is shorter than {} moves", ida.nodes, start.elapsed().as_secs_f64(), threshold);
+                }
+                return (seed, threshold);
+            }
+        }
+    }
+    if config.verbose >= 1 {
+        eprintln!("optimal: the beam solution, {} nodes, {:.2}s", ida.nodes, start.elapsed().as_secs_f64());
+    }
+    (Solution { optimal: true, ..seed }, upper)
+}
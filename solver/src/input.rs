@@ -1,12 +1,77 @@
+use crate::moves::Move;
+use crate::state::State;
 use proconio::input;
+use proconio::source::once::OnceSource;
+use std::io::Read;
 
 pub struct Input {
     pub n: usize,
     pub board: Vec<Vec<i32>>,
 }
 
+impl Input {
+    /// The solved board of size `n`: the blank in the center and the tiles numbered row by row.
+    pub fn solved(n: usize) -> Self {
+        let mut num = 0;
+        let board = (0..2 * n - 1)
+            .map(|i| {
+                (0..2 * n - 1)
+                    .map(|j| {
+                        if i.abs_diff(j) > n - 1 {
+                            -1
+                        } else if (i, j) == (n - 1, n - 1) {
+                            0
+                        } else {
+                            num += 1;
+                            num
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        Input { n, board }
+    }
+
+    /// The solved board of size `n` after `moves`.
+    pub fn scrambled(n: usize, moves: &[Move]) -> Self {
+        let mut state = State::from_input(&Input::solved(n));
+        moves.iter().for_each(|&m| state.apply(m));
+        Input { n, board: state.board }
+    }
+
+    /// Returns the current position of every tile and the position it has to reach.
+    #[allow(clippy::type_complexity)]
+    pub fn positions(&self) -> (Vec<(usize, usize)>, Vec<(usize, usize)>) {
+        let mut tile_positions = vec![(0, 0); (2 * self.n - 1) * (2 * self.n - 1) - self.n * (self.n - 1)];
+        let mut target_positions = vec![(0, 0); (2 * self.n - 1) * (2 * self.n - 1) - self.n * (self.n - 1)];
+        let mut num = 0;
+        for i in 0..2 * self.n - 1 {
+            for j in 0..2 * self.n - 1 {
+                if self.board[i][j] != -1 {
+                    tile_positions[self.board[i][j] as usize] = (i, j);
+                    if (i, j) != (self.n - 1, self.n - 1) {
+                        num += 1;
+                        target_positions[num] = (i, j);
+                    } else {
+                        target_positions[0] = (i, j);
+                    }
+                }
+            }
+        }
+        (tile_positions, target_positions)
+    }
+}
+
 pub fn read_input() -> Input {
+    let mut s = String::new();
+    std::io::stdin().read_to_string(&mut s).unwrap();
+    parse_input(&s)
+}
+
+pub fn parse_input(s: &str) -> Input {
+    let mut source = OnceSource::from(s);
     input! {
+        from &mut source,
         n: usize,
     }
     let mut board = vec![];
@@ -14,11 +79,12 @@ pub fn read_input() -> Input {
         let left_length = (i as i32 - (n as i32 - 1)).max(0) as usize;
         let right_length = (2 * n as i32 - 1 - i as i32 - n as i32).max(0) as usize;
         input! {
+            from &mut source,
             row: [i32; 2 * n - 1 - (n as i32 - 1 - i as i32).unsigned_abs() as usize],
         }
         let left_vec = vec![-1; left_length];
         let right_vec = vec![-1; right_length];
-        let row = left_vec.into_iter().chain(row.into_iter()).chain(right_vec.into_iter()).collect();
+        let row = left_vec.into_iter().chain(row).chain(right_vec).collect();
         board.push(row);
     }
     Input { n, board }
@@ -0,0 +1,258 @@
+pub mod beam_search;
+pub mod bound;
+pub mod budget;
+pub mod constructive;
+pub mod diversity;
+pub mod endgame;
+pub mod evaluator;
+pub mod hash;
+pub mod ida;
+pub mod input;
+pub mod linear;
+pub mod params;
+pub mod pattern;
+pub mod peephole;
+pub mod schedule;
+pub mod stages;
+pub mod state;
+pub mod utils;
+
+pub use torus::{geometry, moves};
+
+use diversity::DiversityPolicy;
+use hash::HashKind;
+use linear::LinearModel;
+use pattern::PatternDatabase;
+use schedule::Schedule;
+use stages::StageOrder;
+use state::BlankTarget;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub use beam_search::{BeamSearch, SearchResult};
+pub use evaluator::{DefaultEvaluator, Evaluator, LinearEvaluator, PatternEvaluator, Weights};
+pub use input::Input;
+pub use moves::Move;
+pub use state::State;
+
+#[derive(Clone)]
+pub struct SolverConfig {
+    /// number of nodes kept at each depth
+    pub beam_width: usize,
+    /// initial size of the node pool (`50 * beam_width` if `None`); also decides the index type
+    pub node_capacity: Option<usize>,
+    /// which candidates compete for the same slots, and how many of them are kept
+    pub diversity: DiversityPolicy,
+    /// candidates considered for selection, as a multiple of `beam_width`, before duplicates are
+    /// dropped; infinite by default, leaving the limit to `diversity`
+    pub candidate_factor: f64,
+    /// exponent applied to the torus distance of each tile
+    pub distance_exponent: f64,
+    /// weight of `State::mismatch_cost` relative to the tile distances
+    pub mismatch_weight: f64,
+    /// weight of the distance of the blank from its target
+    pub blank_weight: f64,
+    /// phases of weights replacing the three above as the search progresses
+    pub schedule: Option<Schedule>,
+    /// weight of the distance of the blank from the misplaced tile chosen by `approach`
+    pub approach_weight: f64,
+    pub approach: BlankTarget,
+    /// tables for `PatternEvaluator`, which replaces the default evaluator when this is not empty
+    pub pattern_databases: Vec<Arc<PatternDatabase>>,
+    /// weight of the pattern databases' term, see `PatternEvaluator`
+    pub pattern_weight: f64,
+    /// weights for `LinearEvaluator`, which replaces the other evaluators when set
+    pub linear_model: Option<LinearModel>,
+    /// solve the tiles stage by stage in this order instead of all at once; always uses
+    /// `DefaultEvaluator`
+    pub stages: Option<StageOrder>,
+    /// weight of the tiles of later stages, relative to those of the current one
+    pub inactive_weight: f64,
+    /// search for a shortest solution with `ida::solve_optimal` instead, never longer than the
+    /// beam search's; within `node_limit`, it proves boards a little over 20 moves from solved
+    pub optimal: bool,
+    /// nodes `ida::solve_optimal` may expand before it keeps the beam solution unproven
+    pub node_limit: Option<u64>,
+    /// solve with `constructive::solve_constructive` instead, which always finishes
+    pub constructive: bool,
+    /// cap the search at the length of the constructive solution and, if it ends unsolved,
+    /// finish its best state with the constructive solver; the shortest result is returned
+    pub fallback: bool,
+    /// rewrite the solution with the shortest equivalent of every window of up to this many moves,
+    /// see `peephole::RewriteTable`
+    pub simplify: Option<usize>,
+    /// once the best score drops below this, try to finish the beam search with `endgame::meet`
+    pub endgame: Option<i64>,
+    /// best candidates the endgame starts from
+    pub endgame_width: usize,
+    /// states near the solved board written by the `endgame` tool: the beam search finishes as
+    /// soon as a candidate is in it, and the endgame uses it instead of the shared one; must
+    /// match the board size and `hash`, or the beam search ignores it
+    pub endgame_table: Option<Arc<endgame::Perimeter>>,
+    /// hash used to drop duplicate candidates
+    pub hash: HashKind,
+    /// compare the full boards of candidates dropped as duplicates and report the hash
+    /// collisions among them; slow, for debugging
+    pub check_collisions: bool,
+    /// wall-clock budget; the beam width is adjusted every depth to finish within it,
+    /// never exceeding `beam_width` nor going below the cap of `diversity`; the search stops at
+    /// it
+    pub time_limit: Option<Duration>,
+    /// 0: silent, 1: progress every 10 depths, 2: progress every depth
+    pub verbose: u8,
+    /// tie-break among candidates with equal score; 0 keeps the order they were generated in
+    pub seed: u64,
+    /// worker threads expanding the search tree
+    pub threads: usize,
+    /// give up after this many moves
+    pub max_depth: Option<usize>,
+    /// give up when the best score has not improved for this many depths
+    pub stall_limit: Option<usize>,
+}
+
+impl Default for SolverConfig {
+    fn default() -> Self {
+        SolverConfig {
+            beam_width: 3000,
+            node_capacity: None,
+            diversity: DiversityPolicy::default(),
+            candidate_factor: f64::INFINITY,
+            distance_exponent: 1.7,
+            mismatch_weight: 1.0,
+            blank_weight: 0.0,
+            schedule: None,
+            approach_weight: 0.0,
+            approach: BlankTarget::Nearest,
+            pattern_databases: vec![],
+            pattern_weight: 0.2,
+            linear_model: None,
+            stages: None,
+            inactive_weight: 0.25,
+            optimal: false,
+            node_limit: Some(5_000_000),
+            constructive: false,
+            fallback: false,
+            simplify: None,
+            endgame: None,
+            endgame_width: 16,
+            endgame_table: None,
+            hash: HashKind::Polynomial,
+            check_collisions: false,
+            time_limit: None,
+            verbose: 0,
+            seed: 0,
+            threads: 1,
+            max_depth: None,
+            stall_limit: Some(2000),
+        }
+    }
+}
+
+impl SolverConfig {
+    pub fn weights(&self) -> Weights {
+        Weights { distance_exponent: self.distance_exponent, mismatch_weight: self.mismatch_weight, blank_weight: self.blank_weight }
+    }
+
+    pub(crate) fn default_evaluator(&self) -> DefaultEvaluator {
+        DefaultEvaluator {
+            mismatch_weight: self.mismatch_weight,
+            blank_weight: self.blank_weight,
+            inactive_weight: 0.0,
+            approach_weight: self.approach_weight,
+            approach: self.approach,
+        }
+    }
+}
+
+pub struct Solution {
+    pub moves: Vec<Move>,
+    /// false when the search stopped early and `constructive::finish` could not complete it;
+    /// `moves` is then the best partial sequence
+    pub solved: bool,
+    /// remaining score after `moves`, as the last search saw it; 0 if `solved`
+    pub score: i64,
+    /// largest number of search tree nodes alive at once
+    pub peak_nodes: usize,
+    /// true if no solution is shorter than `moves`, see `ida::solve_optimal`
+    pub optimal: bool,
+}
+
+impl fmt::Display for Solution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for op in self.moves.iter() {
+            write!(f, "{}", op)?;
+        }
+        Ok(())
+    }
+}
+
+/// Solves `input` in the mode `config` picks. A search that stops unsolved, at the time limit,
+/// `max_depth` or `stall_limit`, is finished by `constructive::finish`.
+pub fn solve(input: &Input, config: &SolverConfig) -> Solution {
+    let solution = solve_unsimplified(input, config);
+    // out of time, stalled or at the depth limit, the search is finished by the solver that
+    // always terminates
+    let solution = constructive::finish(input, solution, config);
+    match config.simplify {
+        Some(length) => peephole::simplify(input, solution, length, config),
+        None => solution,
+    }
+}
+
+fn solve_unsimplified(input: &Input, config: &SolverConfig) -> Solution {
+    if config.constructive {
+        return constructive::solve_constructive(input, config);
+    }
+    if config.fallback {
+        return constructive::solve_with_fallback(input, config);
+    }
+    if config.optimal {
+        return ida::solve_optimal(input, config);
+    }
+    if let Some(order) = config.stages {
+        return stages::solve_staged(input, config, order);
+    }
+    let mut state = State::from_input(input);
+    state.set_hash(config.hash);
+    state.set_distance_exponent(config.distance_exponent);
+    let evaluator = evaluator(&state, config);
+    search(state, None, evaluator, config)
+}
+
+/// The evaluator `config` asks for, for a search from `state`.
+fn evaluator(state: &State, config: &SolverConfig) -> Box<dyn Evaluator> {
+    if let Some(model) = &config.linear_model {
+        Box::new(LinearEvaluator::new(model, &state.geometry))
+    } else if !config.pattern_databases.is_empty() {
+        Box::new(PatternEvaluator::new(state, config.pattern_databases.clone(), config.default_evaluator(), config.pattern_weight))
+    } else {
+        Box::new(config.default_evaluator())
+    }
+}
+
+/// One beam search from `state`, whose last move was `prev_op`.
+fn search(state: State, prev_op: Option<Move>, evaluator: Box<dyn Evaluator>, config: &SolverConfig) -> Solution {
+    let capacity = config.node_capacity.unwrap_or(config.beam_width * 50);
+    if capacity < u32::MAX as usize {
+        let mut beam_search = BeamSearch::<u32>::new(state.clone(), prev_op, config.beam_width, capacity, evaluator);
+        let (solution, pool_full) = run(&mut beam_search, config);
+        if !pool_full {
+            return solution;
+        }
+        // the pool outgrew `u32` indices: search again with room for any size
+        if config.verbose >= 1 {
+            eprintln!("node pool exceeds the u32 index range, searching again with u64 indices");
+        }
+        let evaluator = beam_search.into_evaluator();
+        run(&mut BeamSearch::<u64>::new(state, prev_op, config.beam_width, capacity, evaluator), config).0
+    } else {
+        run(&mut BeamSearch::<u64>::new(state, prev_op, config.beam_width, capacity, evaluator), config).0
+    }
+}
+
+/// The solution of `beam_search`, and whether it stopped because its pool was full.
+fn run<I: beam_search::NodeIndex>(beam_search: &mut BeamSearch<I>, config: &SolverConfig) -> (Solution, bool) {
+    let SearchResult { moves, score, solved, pool_full } = beam_search.solve(config);
+    (Solution { moves, solved, score, peak_nodes: beam_search.peak_nodes(), optimal: false }, pool_full)
+}
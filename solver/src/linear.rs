@@ -0,0 +1,268 @@
+use crate::moves::Move;
+use crate::params::{parse_entries, ParseParamsError};
+use crate::state::State;
+use std::io;
+use std::path::Path;
+
+/// Distances up to this have a histogram feature of their own; longer ones are summed.
+const HISTOGRAM: usize = 12;
+const FAR_DISTANCE: usize = HISTOGRAM + 1;
+const SPANS: usize = HISTOGRAM + 2;
+const BLANK_DISTANCE: usize = HISTOGRAM + 5;
+const WEIGHTED_DISTANCE: usize = HISTOGRAM + 6;
+pub const FEATURES: usize = HISTOGRAM + 7;
+
+/// Feature vector of a state, see `features`.
+pub type Features = [i64; FEATURES];
+
+/// Name of feature `k`, as written in model files.
+pub fn feature_name(k: usize) -> String {
+    match k {
+        0 => "unsolved".to_string(),
+        FAR_DISTANCE => "far_distance".to_string(),
+        BLANK_DISTANCE => "blank_distance".to_string(),
+        WEIGHTED_DISTANCE => "weighted_distance".to_string(),
+        k if k >= SPANS => format!("span_{}", ["i", "j", "k"][k - SPANS]),
+        k => format!("distance_{}", k),
+    }
+}
+
+/// 1 if any tile is misplaced, the number of tiles at torus distance at least `d` for each `d`
+/// from 1 to 12, the summed excess over 12 of the tiles farther than that,
+/// `State::mismatch_spans`, the distance of the blank from its target and the sum of the
+/// `weighted` distances of the tiles. All of them are 0 on the solved state.
+///
+/// The histogram is cumulative so that nonnegative weights make the cost of a tile grow with
+/// its distance. `weighted` is `Geometry::weighted_distances` at the exponent of the model rather
+/// than that of the state, which the search changes.
+///
+/// [`Geometry::weighted_distances`]: crate::geometry::Geometry::weighted_distances
+pub fn features(state: &State, weighted: &[u32]) -> Features {
+    let mut f = [0; FEATURES];
+    f[0] = !state.is_solved() as i64;
+    for i in 1..state.tile_positions.len() {
+        add_distance(&mut f, state.raw_distance(i), 1);
+    }
+    for (axis, span) in state.mismatch_spans().into_iter().enumerate() {
+        f[SPANS + axis] = span as i64;
+    }
+    f[BLANK_DISTANCE] = state.raw_distance(0) as i64;
+    f[WEIGHTED_DISTANCE] = (1..state.tile_positions.len()).map(|i| weighted[state.raw_distance(i) as usize] as i64).sum();
+    f
+}
+
+/// `features` after `m` minus `features` now, without applying `m`.
+pub fn feature_delta(state: &State, m: Move, weighted: &[u32]) -> Features {
+    let a = state.zero_position;
+    let (b, c) = state.geometry.rotated_cells(a, m);
+    let x = state.board[b.0][b.1] as usize;
+    let y = state.board[c.0][c.1] as usize;
+    let distance = |tile: usize, cell| state.geometry.distance(cell, state.target_positions[tile]);
+    let mut f = [0; FEATURES];
+    f[0] = state.is_solved() as i64 - state.is_solved_after(m) as i64;
+    add_distance(&mut f, distance(x, b), -1);
+    add_distance(&mut f, distance(x, c), 1);
+    add_distance(&mut f, distance(y, c), -1);
+    add_distance(&mut f, distance(y, a), 1);
+    let (spans, spans_after) = (state.mismatch_spans(), state.mismatch_spans_after(m));
+    for axis in 0..3 {
+        f[SPANS + axis] = spans_after[axis] as i64 - spans[axis] as i64;
+    }
+    f[BLANK_DISTANCE] = distance(0, b) as i64 - distance(0, a) as i64;
+    let weighted = |tile: usize, cell| weighted[distance(tile, cell) as usize] as i64;
+    f[WEIGHTED_DISTANCE] = weighted(x, c) - weighted(x, b) + weighted(y, a) - weighted(y, c);
+    f
+}
+
+fn add_distance(f: &mut Features, d: u32, sign: i64) {
+    let d = d as usize;
+    for slot in f[1..=d.min(HISTOGRAM)].iter_mut() {
+        *slot += sign;
+    }
+    if d > HISTOGRAM {
+        f[FAR_DISTANCE] += sign * (d - HISTOGRAM) as i64;
+    }
+}
+
+/// Predicts the number of remaining moves as a weighted sum of `features`.
+#[derive(Clone)]
+pub struct LinearModel {
+    pub weights: [f64; FEATURES],
+    /// exponent of the `weighted_distance` feature the weights were fitted on
+    pub distance_exponent: f64,
+}
+
+/// Exponent of the models written before it was recorded.
+const DEFAULT_EXPONENT: f64 = 1.7;
+
+impl LinearModel {
+    pub fn predict(&self, f: &Features) -> f64 {
+        self.weights.iter().zip(f.iter()).map(|(&w, &x)| w * x as f64).sum()
+    }
+
+    /// Reads `feature = weight` lines and the `distance_exponent` as written by `format`; missing
+    /// features weigh 0. Weights must be finite, the exponent positive, and no key may repeat.
+    pub fn parse(text: &str) -> Result<Self, ParseParamsError> {
+        let mut weights = [0.0; FEATURES];
+        let mut distance_exponent = DEFAULT_EXPONENT;
+        let mut seen = vec![];
+        for (line, key, value) in parse_entries(text)? {
+            if seen.contains(&key) {
+                return Err(ParseParamsError { line, message: format!("{} given twice", key) });
+            }
+            seen.push(key);
+            if key == "distance_exponent" {
+                distance_exponent = value
+                    .parse()
+                    .ok()
+                    .filter(|&exponent: &f64| exponent.is_finite() && exponent > 0.0)
+                    .ok_or_else(|| ParseParamsError { line, message: format!("invalid distance exponent {:?}", value) })?;
+                continue;
+            }
+            let Some(k) = (0..FEATURES).find(|&k| feature_name(k) == key) else {
+                return Err(ParseParamsError { line, message: format!("unknown feature {:?}", key) });
+            };
+            weights[k] = value
+                .parse()
+                .ok()
+                .filter(|weight: &f64| weight.is_finite())
+                .ok_or_else(|| ParseParamsError { line, message: format!("invalid weight {:?} for {}", value, key) })?;
+        }
+        Ok(LinearModel { weights, distance_exponent })
+    }
+
+    /// `parse` of the file at `path`, written by the `train` tool.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    pub fn format(&self) -> String {
+        let weights: String = (0..FEATURES).map(|k| format!("{} = {}\n", feature_name(k), self.weights[k])).collect();
+        format!("distance_exponent = {}\n{}", self.distance_exponent, weights)
+    }
+}
+
+/// Normal equations of a least-squares fit, accumulated one sample at a time.
+pub struct LeastSquares {
+    a: [[f64; FEATURES]; FEATURES],
+    b: [f64; FEATURES],
+    count: usize,
+    total_weight: f64,
+    sum_y: f64,
+    sum_yy: f64,
+}
+
+impl Default for LeastSquares {
+    fn default() -> Self {
+        LeastSquares { a: [[0.0; FEATURES]; FEATURES], b: [0.0; FEATURES], count: 0, total_weight: 0.0, sum_y: 0.0, sum_yy: 0.0 }
+    }
+}
+
+impl LeastSquares {
+    /// Adds a sample whose squared error counts `weight` times.
+    pub fn add(&mut self, f: &Features, y: f64, weight: f64) {
+        for k in 0..FEATURES {
+            for l in 0..FEATURES {
+                self.a[k][l] += weight * (f[k] * f[l]) as f64;
+            }
+            self.b[k] += weight * f[k] as f64 * y;
+        }
+        self.count += 1;
+        self.total_weight += weight;
+        self.sum_y += weight * y;
+        self.sum_yy += weight * y * y;
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Weights minimizing the squared error plus `ridge * total weight * |w|^2`, with every
+    /// feature scaled to a unit root mean square first, so that the penalty and the convergence do
+    /// not depend on the units of the features. Features with a `fixed` weight keep it. Solved by
+    /// cyclic coordinate descent; the samples had their `weighted_distance` at `distance_exponent`.
+    pub fn solve(&self, ridge: f64, fixed: &[Option<f64>; FEATURES], distance_exponent: f64) -> LinearModel {
+        let total = self.total_weight.max(f64::MIN_POSITIVE);
+        let scale: [f64; FEATURES] = std::array::from_fn(|k| (self.a[k][k] / total).sqrt());
+        // the weights of the scaled features
+        let mut w: [f64; FEATURES] = std::array::from_fn(|k| fixed[k].unwrap_or(0.0) * scale[k]);
+        for _ in 0..10000 {
+            let mut change: f64 = 0.0;
+            for k in 0..FEATURES {
+                if fixed[k].is_some() || scale[k] == 0.0 {
+                    continue;
+                }
+                let diagonal = self.a[k][k] / (scale[k] * scale[k]) + ridge * total;
+                let rest = (0..FEATURES).filter(|&l| l != k && scale[l] != 0.0).map(|l| self.a[k][l] / (scale[k] * scale[l]) * w[l]).sum::<f64>();
+                let next = (self.b[k] / scale[k] - rest) / diagonal;
+                change = change.max((next - w[k]).abs());
+                w[k] = next;
+            }
+            if change < 1e-9 {
+                break;
+            }
+        }
+        let weights = std::array::from_fn(|k| match fixed[k] {
+            Some(weight) => weight,
+            None if scale[k] == 0.0 => 0.0,
+            None => w[k] / scale[k],
+        });
+        LinearModel { weights, distance_exponent }
+    }
+
+    /// Weighted root mean squared error and coefficient of determination of `model` on the
+    /// samples.
+    pub fn error(&self, model: &LinearModel) -> (f64, f64) {
+        let w = &model.weights;
+        let mut sse = self.sum_yy;
+        for k in 0..FEATURES {
+            sse -= 2.0 * w[k] * self.b[k];
+            for l in 0..FEATURES {
+                sse += w[k] * self.a[k][l] * w[l];
+            }
+        }
+        let total = self.total_weight.max(f64::MIN_POSITIVE);
+        let variance = self.sum_yy - self.sum_y * self.sum_y / total;
+        ((sse.max(0.0) / total).sqrt(), 1.0 - sse / variance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::mix;
+
+    #[test]
+    fn least_squares_recovers_known_weights() {
+        let truth: [f64; FEATURES] = std::array::from_fn(|k| k as f64 * 0.5 - 3.0);
+        let mut least_squares = LeastSquares::default();
+        let mut x = 0;
+        for _ in 0..1000 {
+            let f: Features = std::array::from_fn(|k| {
+                x = mix(x + 1);
+                // features of very different scales, as the real ones
+                (x % 10) as i64 * [1, 10, 1000][k % 3]
+            });
+            let y = truth.iter().zip(f.iter()).map(|(&w, &v)| w * v as f64).sum();
+            least_squares.add(&f, y, 1.0);
+        }
+        let model = least_squares.solve(0.0, &[None; FEATURES], DEFAULT_EXPONENT);
+        for (k, (&weight, &expected)) in model.weights.iter().zip(truth.iter()).enumerate() {
+            assert!((weight - expected).abs() < 1e-6, "{}: {} instead of {}", feature_name(k), weight, expected);
+        }
+        let (rmse, _) = least_squares.error(&model);
+        assert!(rmse < 1e-3, "rmse {}", rmse);
+    }
+
+    #[test]
+    fn parse_checks_its_input() {
+        let model = LinearModel { weights: std::array::from_fn(|k| k as f64 - 4.5), distance_exponent: 1.5 };
+        let parsed = LinearModel::parse(&model.format()).unwrap();
+        assert_eq!(parsed.weights, model.weights);
+        assert_eq!(parsed.distance_exponent, model.distance_exponent);
+        for text in ["unsolved = 1\nunsolved = 2", "unsolved = NaN", "unsolved = inf", "distance_exponent = 0", "distance_exponent = -1", "nonsense = 1", "unsolved"] {
+            assert!(LinearModel::parse(text).is_err(), "{:?} was accepted", text);
+        }
+    }
+}
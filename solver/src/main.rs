@@ -1,34 +1,235 @@
-mod beam_search;
-mod input;
-mod state;
-mod utils;
+use clap::Parser;
+use solver::diversity::DiversityPolicy;
+use solver::endgame::Perimeter;
+use solver::hash::HashKind;
+use solver::linear::LinearModel;
+use solver::params::parse_candidate_factor;
+use solver::pattern::PatternDatabase;
+use solver::stages::StageOrder;
+use solver::state::BlankTarget;
+use solver::SolverConfig;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(about = "Beam search solver for Balto's Puzzle")]
+struct Args {
+    /// input file (stdin if omitted)
+    #[arg(short, long)]
+    input: Option<PathBuf>,
+    /// output file (stdout if omitted)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// number of nodes kept at each depth
+    #[arg(short = 'w', long, default_value_t = 3000, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    beam_width: usize,
+    /// initial size of the node pool (50 * beam width if omitted)
+    #[arg(long)]
+    node_capacity: Option<usize>,
+    /// parameter file, overridden by the flags below (see `params.txt`)
+    #[arg(short, long)]
+    params: Option<PathBuf>,
+    /// what candidates share a diversity key: `cell` (blank cell), `cell-move` (blank cell and
+    /// last move) or `region[:radius]` (tiles around the blank) [default: cell]
+    #[arg(long)]
+    diversity: Option<DiversityPolicy>,
+    /// maximum number of candidates kept per diversity key [default: 10 for cell, 2 for cell-move, 1 for region]
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    diversity_cap: Option<usize>,
+    /// candidates considered for selection, as a multiple of the beam width, at least 1 [default: inf]
+    #[arg(long, value_parser = parse_candidate_factor)]
+    candidate_factor: Option<f64>,
+    /// exponent applied to the torus distance of each tile [default: 1.7]
+    #[arg(long)]
+    distance_exponent: Option<f64>,
+    /// weight of the mismatch cost relative to the tile distances [default: 1]
+    #[arg(long)]
+    mismatch_weight: Option<f64>,
+    /// weight of the distance of the blank from its target [default: 0]
+    #[arg(long)]
+    blank_weight: Option<f64>,
+    /// weight of the distance of the blank from the misplaced tile chosen by `--approach` [default: 0]
+    #[arg(long)]
+    approach_weight: Option<f64>,
+    /// misplaced tile the blank is drawn to: `nearest`, or `farthest` from its target [default: nearest]
+    #[arg(long)]
+    approach: Option<BlankTarget>,
+    /// pattern database built by `pdb`, enabling the pattern evaluator (repeatable; all additive or
+    /// none)
+    #[arg(long)]
+    pdb: Vec<PathBuf>,
+    /// weight of the summed values of additive pattern databases, or of the moves other ones need
+    /// beyond the tile distances [default: 0.2]
+    #[arg(long)]
+    pattern_weight: Option<f64>,
+    /// linear model written by `train`, replacing the heuristic parameters above
+    #[arg(long)]
+    model: Option<PathBuf>,
+    /// place the tiles stage by stage: `rings` (outer ring first) or `rows` (top row first)
+    #[arg(long)]
+    stages: Option<StageOrder>,
+    /// weight of the tiles of later stages in the staged mode [default: 0.25]
+    #[arg(long)]
+    inactive_weight: Option<f64>,
+    /// time budget in seconds; the beam width is adapted every depth to finish within it, and a
+    /// search still unsolved at the limit is finished constructively
+    #[arg(short, long)]
+    time_limit: Option<f64>,
+    /// 0: silent, 1: progress every 10 depths, 2: progress every depth
+    #[arg(short, long, default_value_t = 1)]
+    verbose: u8,
+    /// tie-break among candidates with equal score (0: the order they were generated in)
+    #[arg(short, long, default_value_t = 0)]
+    seed: u64,
+    /// worker threads expanding the search tree (0: all available cores)
+    #[arg(short = 'j', long, default_value_t = 1)]
+    threads: usize,
+    /// search for a shortest solution with IDA*, below the length of the beam search's; at the
+    /// node or time limit, keeps the beam solution unproven. Proves boards a little over 20 moves
+    /// from solved; it does not prove full n = 3 instances, around 27 moves
+    #[arg(long)]
+    optimal: bool,
+    /// nodes IDA* may expand with `--optimal`; a million take a few seconds, depending on the
+    /// machine (0 disables)
+    #[arg(long, default_value_t = 5_000_000)]
+    node_limit: u64,
+    /// solve with the constructive 3-cycle solver only
+    #[arg(long)]
+    constructive: bool,
+    /// bound the search by the constructive solution and finish it constructively if it fails
+    #[arg(long)]
+    fallback: bool,
+    /// shorten the solution by rewriting every window of up to this many moves (8 takes about a
+    /// second)
+    #[arg(long)]
+    simplify: Option<usize>,
+    /// finish with a bidirectional search once the best score drops below this
+    #[arg(long)]
+    endgame: Option<i64>,
+    /// best states the endgame starts from
+    #[arg(long, default_value_t = 16)]
+    endgame_width: usize,
+    /// endgame table built by `endgame`: the search finishes as soon as it reaches a state in it
+    #[arg(long)]
+    endgame_table: Option<PathBuf>,
+    /// hash of the states, used to drop duplicates: `polynomial` or `zobrist`
+    #[arg(long, default_value = "polynomial")]
+    hash: HashKind,
+    /// compare the full boards behind equal hashes and report the collisions (slow)
+    #[arg(long)]
+    check_collisions: bool,
+    /// give up after this many moves
+    #[arg(long)]
+    max_depth: Option<usize>,
+    /// give up when the best score has not improved for this many depths (0 disables)
+    #[arg(long, default_value_t = 2000)]
+    stall_limit: usize,
+}
 
 fn main() {
-    let input = input::read_input();
+    let args = Args::parse();
 
-    let mut tile_positions = vec![(0, 0); (2 * input.n - 1) * (2 * input.n - 1) - input.n * (input.n - 1)];
-    let mut target_positions = vec![(0, 0); (2 * input.n - 1) * (2 * input.n - 1) - input.n * (input.n - 1)];
-    let mut num = 0;
-    for i in 0..2 * input.n - 1 {
-        for j in 0..2 * input.n - 1 {
-            if input.board[i][j] != -1 {
-                tile_positions[input.board[i][j] as usize] = (i, j);
-                if (i, j) != (input.n - 1, input.n - 1) {
-                    num += 1;
-                    target_positions[num] = (i, j);
-                } else {
-                    target_positions[0] = (i, j);
-                }
+    let input = match &args.input {
+        Some(path) => solver::input::parse_input(&std::fs::read_to_string(path).expect("failed to read input")),
+        None => solver::input::read_input(),
+    };
+    let mut config = SolverConfig::default();
+    if let Some(path) = &args.params {
+        let text = std::fs::read_to_string(path).expect("failed to read parameter file");
+        if let Err(e) = solver::params::parse_params(&text, &mut config) {
+            eprintln!("{}: {}", path.display(), e);
+            std::process::exit(2);
+        }
+    }
+    config.diversity = args.diversity.unwrap_or(config.diversity);
+    config.diversity = config.diversity.with_cap(args.diversity_cap.unwrap_or(config.diversity.cap()));
+    config.candidate_factor = args.candidate_factor.unwrap_or(config.candidate_factor);
+    config.distance_exponent = args.distance_exponent.unwrap_or(config.distance_exponent);
+    config.mismatch_weight = args.mismatch_weight.unwrap_or(config.mismatch_weight);
+    config.blank_weight = args.blank_weight.unwrap_or(config.blank_weight);
+    config.pattern_weight = args.pattern_weight.unwrap_or(config.pattern_weight);
+    config.approach_weight = args.approach_weight.unwrap_or(config.approach_weight);
+    config.approach = args.approach.unwrap_or(config.approach);
+    config.inactive_weight = args.inactive_weight.unwrap_or(config.inactive_weight);
+    for path in args.pdb.iter() {
+        match PatternDatabase::load(path) {
+            Ok(database) if database.n() != input.n => {
+                eprintln!("{}: built for n = {}, but the input has n = {}", path.display(), database.n(), input.n);
+                std::process::exit(2);
+            }
+            Ok(database) if config.pattern_databases.first().is_some_and(|first| first.is_additive() != database.is_additive()) => {
+                eprintln!("{}: additive and other pattern databases cannot be mixed", path.display());
+                std::process::exit(2);
+            }
+            Ok(database) => config.pattern_databases.push(Arc::new(database)),
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                std::process::exit(2);
             }
         }
     }
+    if let Some(path) = &args.model {
+        match LinearModel::load(path) {
+            Ok(model) => config.linear_model = Some(model),
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                std::process::exit(2);
+            }
+        }
+    }
+    if let Some(path) = &args.endgame_table {
+        match Perimeter::load(path) {
+            Ok(table) if table.n() == input.n && table.hash() == args.hash => config.endgame_table = Some(Arc::new(table)),
+            Ok(table) if table.n() != input.n => {
+                eprintln!("{}: built for n = {}, but the input has n = {}", path.display(), table.n(), input.n);
+                std::process::exit(2);
+            }
+            Ok(table) => {
+                eprintln!("{}: built with the {:?} hash, but the search uses {:?}", path.display(), table.hash(), args.hash);
+                std::process::exit(2);
+            }
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                std::process::exit(2);
+            }
+        }
+    }
+    let config = SolverConfig {
+        beam_width: args.beam_width,
+        node_capacity: args.node_capacity,
+        time_limit: args.time_limit.map(Duration::from_secs_f64),
+        verbose: args.verbose,
+        seed: args.seed,
+        threads: match args.threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            threads => threads,
+        },
+        stages: args.stages.or(config.stages),
+        optimal: args.optimal,
+        node_limit: (args.node_limit != 0).then_some(args.node_limit),
+        constructive: args.constructive,
+        fallback: args.fallback,
+        simplify: args.simplify,
+        endgame: args.endgame,
+        endgame_width: args.endgame_width,
+        hash: args.hash,
+        check_collisions: args.check_collisions,
+        max_depth: args.max_depth,
+        stall_limit: (args.stall_limit != 0).then_some(args.stall_limit),
+        ..config
+    };
+    let solution = solver::solve(&input, &config);
 
-    let state = state::State::new(&input, tile_positions, target_positions);
-    let mut beam_search = beam_search::BeamSearch::new(state, '!');
-    let output = beam_search.solve(&input);
-
-    for op in output.iter() {
-        print!("{}", op);
+    match &args.output {
+        Some(path) => std::fs::write(path, format!("{}\n", solution)).expect("failed to write output"),
+        None => println!("{}", solution),
+    }
+    if args.optimal {
+        eprintln!("{} moves, {}", solution.moves.len(), if solution.optimal { "proven optimal" } else { "not proven optimal" });
+    }
+    if !solution.solved {
+        eprintln!("not solved: {} moves, remaining score {}", solution.moves.len(), solution.score);
+        std::process::exit(1);
     }
-    println!();
 }
@@ -0,0 +1,253 @@
+use crate::evaluator::Weights;
+use crate::linear::LinearModel;
+use crate::schedule::{Phase, PhaseKey, Schedule};
+use crate::SolverConfig;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug)]
+pub struct ParseParamsError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseParamsError {}
+
+/// Overwrites the parameters of `config` found in `text`, one `key = value` per line, each key
+/// once. Blank lines and everything after `#` are ignored.
+///
+/// ```text
+/// distance_exponent = 1.7
+/// mismatch_weight = 1.0
+/// blank_weight = 0.0
+/// diversity_cap = 10
+/// candidate_factor = inf
+/// pattern_weight = 0.2
+/// ```
+///
+/// `diversity = cell`, `cell-move` or `region[:radius]` picks a `DiversityPolicy` with its own
+/// default cap, which a later `diversity_cap` overrides. `approach_weight` and
+/// `approach = nearest` or `farthest` set the blank's distance term, see `DefaultEvaluator`;
+/// `stages = rings` or `rows` turns on the staged mode, with `inactive_weight`. `model = <file>`
+/// loads a `LinearModel` written by the `train` tool, which replaces the evaluator.
+///
+/// A `schedule` line, keyed on `depth` or `score`, starts a `Schedule`; each following `phase`
+/// line gives the point where it starts and the weights that change, the others carrying over
+/// from the previous phase (from the values above for the first one).
+///
+/// ```text
+/// schedule = score
+/// interpolate = true
+/// phase = 1e9
+/// phase = 2000 mismatch_weight=3 blank_weight=2
+/// ```
+pub fn parse_params(text: &str, config: &mut SolverConfig) -> Result<(), ParseParamsError> {
+    let mut schedule_line = 0;
+    let mut cap_line = 0;
+    // line of each key but `phase`, which repeats
+    let mut seen: Vec<(&str, usize)> = vec![];
+    for (line, key, value) in parse_entries(text)? {
+        let invalid = || ParseParamsError { line, message: format!("invalid value {:?} for {}", value, key) };
+        if let Some(&(_, first)) = seen.iter().find(|&&(seen, _)| seen == key) {
+            return Err(ParseParamsError { line, message: format!("{} already given on line {}", key, first) });
+        }
+        if key != "phase" {
+            seen.push((key, line));
+        }
+        match key {
+            "blank_weight" => config.blank_weight = value.parse().map_err(|_| invalid())?,
+            "stages" => config.stages = Some(value.parse().map_err(|_| invalid())?),
+            "approach_weight" => config.approach_weight = value.parse().map_err(|_| invalid())?,
+            "approach" => config.approach = value.parse().map_err(|_| invalid())?,
+            "inactive_weight" => config.inactive_weight = value.parse().map_err(|_| invalid())?,
+            "schedule" => {
+                let key = match value {
+                    "depth" => PhaseKey::Depth,
+                    "score" => PhaseKey::Score,
+                    _ => return Err(invalid()),
+                };
+                config.schedule = Some(Schedule { key, phases: vec![], interpolate: false });
+                schedule_line = line;
+            }
+            "interpolate" | "phase" if config.schedule.is_none() => {
+                return Err(ParseParamsError { line, message: format!("{} before schedule", key) });
+            }
+            "interpolate" => config.schedule.as_mut().unwrap().interpolate = value.parse().map_err(|_| invalid())?,
+            "phase" => {
+                let weights = config.weights();
+                let schedule = config.schedule.as_mut().unwrap();
+                let mut words = value.split_whitespace();
+                let at = words.next().and_then(|at| at.parse().ok()).ok_or_else(invalid)?;
+                let mut weights = schedule.phases.last().map_or(weights, |phase| phase.weights);
+                for word in words {
+                    let (name, weight) = word.split_once('=').ok_or_else(invalid)?;
+                    let weight = weight.parse().map_err(|_| invalid())?;
+                    match name {
+                        "distance_exponent" => weights.distance_exponent = weight,
+                        "mismatch_weight" => weights.mismatch_weight = weight,
+                        "blank_weight" => weights.blank_weight = weight,
+                        _ => return Err(ParseParamsError { line, message: format!("unknown weight {:?}", name) }),
+                    }
+                }
+                if let Some(last) = schedule.phases.last() {
+                    let ordered = match schedule.key {
+                        PhaseKey::Depth => at > last.at,
+                        PhaseKey::Score => at < last.at,
+                    };
+                    if !ordered {
+                        return Err(ParseParamsError { line, message: "phases are out of order".to_string() });
+                    }
+                }
+                schedule.phases.push(Phase { at, weights });
+            }
+            "distance_exponent" => config.distance_exponent = value.parse().map_err(|_| invalid())?,
+            "mismatch_weight" => config.mismatch_weight = value.parse().map_err(|_| invalid())?,
+            "diversity" if cap_line != 0 => {
+                return Err(ParseParamsError { line, message: format!("diversity after diversity_cap on line {}", cap_line) });
+            }
+            "diversity" => config.diversity = value.parse().map_err(|_| invalid())?,
+            "diversity_cap" => {
+                config.diversity = config.diversity.with_cap(value.parse().ok().filter(|&cap| cap >= 1).ok_or_else(invalid)?);
+                cap_line = line;
+            }
+            "candidate_factor" => config.candidate_factor = parse_candidate_factor(value).map_err(|_| invalid())?,
+            "pattern_weight" => config.pattern_weight = value.parse().map_err(|_| invalid())?,
+            "model" => {
+                let model = LinearModel::load(Path::new(value)).map_err(|e| ParseParamsError { line, message: format!("model {}: {}", value, e) })?;
+                config.linear_model = Some(model);
+            }
+            _ => return Err(ParseParamsError { line, message: format!("unknown parameter {:?}", key) }),
+        }
+    }
+    if config.schedule.as_ref().is_some_and(|schedule| schedule.phases.is_empty()) {
+        return Err(ParseParamsError { line: schedule_line, message: "schedule without phases".to_string() });
+    }
+    Ok(())
+}
+
+/// `value` as a `candidate_factor`, which must be at least 1 so that every depth keeps a
+/// candidate.
+pub fn parse_candidate_factor(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(factor) if factor >= 1.0 => Ok(factor),
+        Ok(_) => Err("must be at least 1".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// The `(line, key, value)` of every `key = value` line of `text`, skipping blank lines and
+/// everything after `#`.
+pub fn parse_entries(text: &str) -> Result<Vec<(usize, &str, &str)>, ParseParamsError> {
+    let mut entries = vec![];
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(ParseParamsError { line: idx + 1, message: format!("expected `key = value`, found {:?}", line) });
+        };
+        entries.push((idx + 1, key.trim(), value.trim()));
+    }
+    Ok(entries)
+}
+
+/// The parameters of `config` in the format read by `parse_params`.
+pub fn format_params(config: &SolverConfig) -> String {
+    let mut text = format!(
+        "distance_exponent = {}\nmismatch_weight = {}\nblank_weight = {}\ndiversity = {}\ndiversity_cap = {}\ncandidate_factor = {}\npattern_weight = {}\n",
+        config.distance_exponent,
+        config.mismatch_weight,
+        config.blank_weight,
+        config.diversity,
+        config.diversity.cap(),
+        config.candidate_factor,
+        config.pattern_weight
+    );
+    if config.approach_weight != 0.0 {
+        text += &format!("approach_weight = {}\napproach = {}\n", config.approach_weight, config.approach);
+    }
+    if let Some(schedule) = &config.schedule {
+        let key = match schedule.key {
+            PhaseKey::Depth => "depth",
+            PhaseKey::Score => "score",
+        };
+        text += &format!("schedule = {}\ninterpolate = {}\n", key, schedule.interpolate);
+        for phase in schedule.phases.iter() {
+            let Weights { distance_exponent, mismatch_weight, blank_weight } = phase.weights;
+            text += &format!("phase = {} distance_exponent={} mismatch_weight={} blank_weight={}\n", phase.at, distance_exponent, mismatch_weight, blank_weight);
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The line and message of the error `text` gives.
+    fn error(text: &str) -> (usize, String) {
+        let e = parse_params(text, &mut SolverConfig::default()).unwrap_err();
+        (e.line, e.message)
+    }
+
+    #[test]
+    fn blank_lines_and_comments_are_skipped() {
+        let mut config = SolverConfig::default();
+        parse_params("\n# comment\n   \nmismatch_weight = 2.5  # trailing\n\n\tblank_weight=0.5\n", &mut config).unwrap();
+        assert_eq!((config.mismatch_weight, config.blank_weight), (2.5, 0.5));
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let (line, message) = error("mismatch_weight = 1\nmismatch_wieght = 2");
+        assert_eq!(line, 2);
+        assert!(message.contains("mismatch_wieght"), "{}", message);
+    }
+
+    #[test]
+    fn duplicate_keys_are_rejected() {
+        assert_eq!(error("blank_weight = 1\n\nblank_weight = 2"), (3, "blank_weight already given on line 1".to_string()));
+        // but phases repeat
+        let mut config = SolverConfig::default();
+        parse_params("schedule = depth\nphase = 0\nphase = 10 blank_weight=1", &mut config).unwrap();
+        assert_eq!(config.schedule.unwrap().phases.len(), 2);
+    }
+
+    #[test]
+    fn phases_out_of_order_or_overlapping_are_rejected() {
+        for text in [
+            "schedule = depth\nphase = 10\nphase = 5",
+            "schedule = depth\nphase = 10\nphase = 10",
+            "schedule = score\nphase = 100\nphase = 200",
+            "schedule = score\nphase = 100\nphase = 100",
+        ] {
+            assert_eq!(error(text), (3, "phases are out of order".to_string()), "{:?}", text);
+        }
+        assert_eq!(error("phase = 10").1, "phase before schedule");
+        assert_eq!(error("schedule = depth").1, "schedule without phases");
+    }
+
+    #[test]
+    fn malformed_values_are_rejected() {
+        for text in ["mismatch_weight = 1.2.3", "blank_weight = one", "distance_exponent =", "diversity_cap = -1", "diversity_cap = 2.5", "approach = sideways", "blank_weight 1"] {
+            assert_eq!(error(text).0, 1, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn values_that_empty_the_beam_are_rejected() {
+        for text in ["diversity_cap = 0", "candidate_factor = 0", "candidate_factor = 0.5", "candidate_factor = NaN"] {
+            assert!(parse_params(text, &mut SolverConfig::default()).is_err(), "{:?} was accepted", text);
+        }
+        let mut config = SolverConfig::default();
+        parse_params("diversity_cap = 1\ncandidate_factor = 1", &mut config).unwrap();
+        assert_eq!((config.diversity.cap(), config.candidate_factor), (1, 1.0));
+    }
+}
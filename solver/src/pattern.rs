@@ -0,0 +1,330 @@
+use crate::geometry::Geometry;
+use crate::moves::Move;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"BPDB";
+const ADDITIVE_MAGIC: &[u8; 4] = b"BPDA";
+
+/// Exact number of moves to bring a group of tiles to their targets, for every placement of
+/// those tiles and the blank, with every other tile treated as indistinguishable.
+///
+/// The torus is translation invariant, so one table serves every group of the same `shape`:
+/// positions are stored relative to the first target, moved to the center cell. The blank may
+/// end anywhere, and the direction of the next move is not restricted (the table keeps the
+/// smaller of the two values).
+///
+/// An additive table (`build_additive`) counts only the steps of the group's own tiles instead,
+/// the other moves being free. Every move takes two tiles one step, so half the sum over
+/// disjoint groups is a lower bound on the moves left.
+pub struct PatternDatabase {
+    n: usize,
+    cells: usize,
+    shape: Vec<(i32, i32)>,
+    additive: bool,
+    table: Vec<u8>,
+    /// smallest value over the blank cells, per placement of the tiles
+    any_blank: Vec<u8>,
+}
+
+impl PatternDatabase {
+    /// Entries of the table for `tiles` tiles on a board of size `n`, or `None` if `build` cannot
+    /// index its BFS queue with `u32`.
+    pub fn table_len(n: usize, tiles: usize) -> Option<usize> {
+        let cells = Geometry::get(n).cells().len();
+        let len = cells.checked_pow(tiles as u32 + 1)?;
+        (len.checked_mul(2)? <= u32::MAX as usize).then_some(len)
+    }
+
+    /// Backward BFS from the solved placements of a group whose targets are at `shape`
+    /// relative to the first one. Memory is `2 * cells^(shape.len() + 1)` bytes; panics if
+    /// `table_len` is `None`.
+    pub fn build(n: usize, shape: &[(i32, i32)]) -> Self {
+        let table = distances(n, shape, false);
+        Self::new(n, shape.to_vec(), false, table)
+    }
+
+    /// `build` for the additive table, see `PatternDatabase`.
+    pub fn build_additive(n: usize, shape: &[(i32, i32)]) -> Self {
+        let table = distances(n, shape, true);
+        Self::new(n, shape.to_vec(), true, table)
+    }
+
+    fn new(n: usize, shape: Vec<(i32, i32)>, additive: bool, table: Vec<u8>) -> Self {
+        let cells = Geometry::get(n).cells().len();
+        // the blank is the last digit of `encode`, so each chunk is one placement of the tiles
+        let any_blank = table.chunks(cells).map(|row: &[u8]| *row.iter().min().unwrap()).collect();
+        PatternDatabase { n, cells, shape, additive, table, any_blank }
+    }
+
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    /// Whether the table was built by `build_additive`.
+    pub fn is_additive(&self) -> bool {
+        self.additive
+    }
+
+    /// Offsets of the group's targets from the first one.
+    pub fn shape(&self) -> &[(i32, i32)] {
+        &self.shape
+    }
+
+    /// Largest finite value of the table.
+    pub fn max_value(&self) -> u8 {
+        self.table.iter().copied().filter(|&v| v != u8::MAX).max().unwrap_or(0)
+    }
+
+    /// Moves left with the blank at cell id `blank` and the group's tiles at cell ids `tiles`,
+    /// both already relative to the center (see `PatternEvaluator`).
+    ///
+    /// [`PatternEvaluator`]: crate::evaluator::PatternEvaluator
+    pub fn get(&self, blank: usize, tiles: &[usize]) -> u8 {
+        self.table[encode(blank, tiles, self.cells)]
+    }
+
+    /// Smallest `get` over every cell of the blank.
+    pub fn get_any_blank(&self, tiles: &[usize]) -> u8 {
+        self.any_blank[encode(0, tiles, self.cells) / self.cells]
+    }
+
+    /// Writes the table; the magic tells additive tables apart.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+        writer.write_all(if self.additive { ADDITIVE_MAGIC } else { MAGIC })?;
+        writer.write_all(&(self.n as u32).to_le_bytes())?;
+        writer.write_all(&(self.shape.len() as u32).to_le_bytes())?;
+        for &(di, dj) in self.shape.iter() {
+            writer.write_all(&di.to_le_bytes())?;
+            writer.write_all(&dj.to_le_bytes())?;
+        }
+        writer.write_all(&self.table)?;
+        writer.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut reader = io::BufReader::new(std::fs::File::open(path)?);
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let mut word = [0; 4];
+        reader.read_exact(&mut word)?;
+        let additive = match &word {
+            MAGIC => false,
+            ADDITIVE_MAGIC => true,
+            _ => return Err(invalid("not a pattern database")),
+        };
+        let mut read_u32 = || -> io::Result<u32> {
+            reader.read_exact(&mut word)?;
+            Ok(u32::from_le_bytes(word))
+        };
+        let n = read_u32()? as usize;
+        let size = read_u32()? as usize;
+        if n < 2 || !(1..=4).contains(&size) {
+            return Err(invalid("corrupt pattern database header"));
+        }
+        let mut shape = vec![];
+        for _ in 0..size {
+            shape.push((read_u32()? as i32, read_u32()? as i32));
+        }
+        if shape[0] != (0, 0) {
+            return Err(invalid("corrupt pattern database header"));
+        }
+        let mut table = vec![];
+        reader.read_to_end(&mut table)?;
+        // the board has more than n^2 cells; checked before building its geometry
+        if n.checked_mul(n).is_none_or(|cells| cells > table.len()) {
+            return Err(invalid("pattern database has the wrong size"));
+        }
+        if Geometry::get(n).cells().len().checked_pow(size as u32 + 1) != Some(table.len()) {
+            return Err(invalid("pattern database has the wrong size"));
+        }
+        Ok(Self::new(n, shape, additive, table))
+    }
+}
+
+/// Backward search from the solved placements of the group at `shape`, moves costing 1 or, if
+/// `additive`, the number of the group's tiles they move; the smaller value over the direction of
+/// the last move, per placement. Costs are small, so the queue is a list per cost.
+fn distances(n: usize, shape: &[(i32, i32)], additive: bool) -> Vec<u8> {
+    assert!(shape.first() == Some(&(0, 0)), "shape must start at (0, 0)");
+    let geometry = Geometry::get(n);
+    let cells = geometry.cells().len();
+    let len = PatternDatabase::table_len(n, shape.len()).expect("pattern database too large");
+
+    let origin = (n - 1, n - 1);
+    let targets = shape.iter().map(|&offset| geometry.cell_id(geometry.translate(origin, offset))).collect::<Vec<_>>();
+    // dist[2 * idx + d]: moves left from placement idx when the last move was clockwise (d = 0)
+    let mut dist = vec![u8::MAX; 2 * len];
+    let mut queues: Vec<Vec<u32>> = vec![vec![]];
+    for blank in 0..cells {
+        if targets.contains(&blank) {
+            continue;
+        }
+        let idx = encode(blank, &targets, cells);
+        for d in 0..2 {
+            dist[2 * idx + d] = 0;
+            queues[0].push((2 * idx + d) as u32);
+        }
+    }
+
+    let mut tiles = vec![0; shape.len()];
+    let mut before = vec![0; shape.len()];
+    let mut cost = 0;
+    while cost < queues.len() {
+        // moves of no tile of the group refill the queue being emptied
+        let Some(key) = queues[cost].pop() else {
+            cost += 1;
+            continue;
+        };
+        let key = key as usize;
+        if dist[key] as usize != cost {
+            continue;
+        }
+        let blank = decode(key / 2, &mut tiles, cells);
+        let clockwise = key.is_multiple_of(2);
+        // predecessors: the last move `m` has the direction of `key`, and the move before it had
+        // the other one
+        for m in Move::ALL.into_iter().filter(|m| m.is_clockwise() == clockwise) {
+            before.copy_from_slice(&tiles);
+            let blank = rotate(&geometry, blank, &mut before, m.inverse());
+            let prev = 2 * encode(blank, &before, cells) + clockwise as usize;
+            let step = if additive { before.iter().zip(tiles.iter()).filter(|(a, b)| a != b).count() } else { 1 };
+            if cost + step < dist[prev] as usize {
+                assert!(cost + step < u8::MAX as usize, "pattern database distance overflow");
+                dist[prev] = (cost + step) as u8;
+                if queues.len() <= cost + step {
+                    queues.resize(cost + step + 1, vec![]);
+                }
+                queues[cost + step].push(prev as u32);
+            }
+        }
+    }
+    dist.chunks(2).map(|d| d[0].min(d[1])).collect()
+}
+
+fn encode(blank: usize, tiles: &[usize], cells: usize) -> usize {
+    tiles.iter().rev().fold(0, |idx, &tile| idx * cells + tile) * cells + blank
+}
+
+fn decode(idx: usize, tiles: &mut [usize], cells: usize) -> usize {
+    let mut rest = idx / cells;
+    for tile in tiles.iter_mut() {
+        *tile = rest % cells;
+        rest /= cells;
+    }
+    idx % cells
+}
+
+/// Applies `m` to a placement given by cell ids and returns the new blank.
+fn rotate(geometry: &Geometry, blank: usize, tiles: &mut [usize], m: Move) -> usize {
+    let a = geometry.cells()[blank];
+    let (b, c) = geometry.rotated_cells(a, m);
+    let (a, b, c) = (blank, geometry.cell_id(b), geometry.cell_id(c));
+    for tile in tiles.iter_mut() {
+        if *tile == b {
+            *tile = c;
+        } else if *tile == c {
+            *tile = a;
+        }
+    }
+    b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Input;
+    use crate::state::State;
+    use crate::utils::random_moves;
+
+    /// Value of `database` for the group of tiles whose first target is `target`, on `state`.
+    fn value(database: &PatternDatabase, state: &State, target: (usize, usize)) -> u8 {
+        let n = state.n();
+        let geometry = &state.geometry;
+        let shift = (n as i32 - 1 - target.0 as i32, n as i32 - 1 - target.1 as i32);
+        let relative = |cell| geometry.cell_id(geometry.translate(cell, shift));
+        let solved = Input::solved(n);
+        let tiles: Vec<usize> = database
+            .shape()
+            .iter()
+            .map(|&offset| {
+                let (i, j) = geometry.translate(target, offset);
+                relative(state.tile_positions[solved.board[i][j] as usize])
+            })
+            .collect();
+        database.get(relative(state.zero_position), &tiles)
+    }
+
+    #[test]
+    fn values_bound_short_scrambles() {
+        let n = 3;
+        let databases = [PatternDatabase::build(n, &[(0, 0)]), PatternDatabase::build(n, &[(0, 0), (0, 1)]), PatternDatabase::build(n, &[(0, 0), (1, 1)])];
+        let solved = State::from_input(&Input::solved(n));
+        // the first target of every group whose targets avoid the blank's
+        let groups = |database: &PatternDatabase| -> Vec<(usize, usize)> {
+            let avoids_blank = |&target: &(usize, usize)| database.shape().iter().all(|&offset| solved.geometry.translate(target, offset) != solved.target_positions[0]);
+            solved.target_positions[1..].iter().copied().filter(avoids_blank).collect()
+        };
+        for database in databases.iter() {
+            for target in groups(database) {
+                assert_eq!(value(database, &solved, target), 0, "{:?} at {:?}: not 0 on the solved board", database.shape(), target);
+            }
+        }
+        let mut positive = 0;
+        for k in 1..=6 {
+            for seed in 0..10 {
+                let state = State::from_input(&Input::scrambled(n, &random_moves(k, seed)));
+                for database in databases.iter() {
+                    for target in groups(database) {
+                        let value = value(database, &state, target);
+                        assert!(value as usize <= k, "{:?} at {:?}: {} after {} moves", database.shape(), target, value, k);
+                        positive += (value > 0) as usize;
+                    }
+                }
+            }
+        }
+        assert!(positive > 0, "every value is 0");
+    }
+
+    #[test]
+    fn saved_table_loads_back() {
+        let path = std::env::temp_dir().join(format!("pdb-{}-round-trip.bin", std::process::id()));
+        for database in [PatternDatabase::build(3, &[(0, 0), (0, 1)]), PatternDatabase::build_additive(3, &[(0, 0), (1, 1)])] {
+            database.save(&path).unwrap();
+            let loaded = PatternDatabase::load(&path).unwrap();
+            let additive = database.is_additive();
+            assert!(loaded.n == database.n && loaded.shape == database.shape && loaded.additive == additive, "additive = {}: header changed", additive);
+            assert!(loaded.table == database.table, "additive = {}: table changed", additive);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn additive_values_bound_short_scrambles() {
+        let n = 3;
+        let solved = State::from_input(&Input::solved(n));
+        let single = PatternDatabase::build_additive(n, &[(0, 0)]);
+        // pairs of consecutive tiles, with the additive table of their shape
+        let pairs: Vec<((usize, usize), PatternDatabase)> = (1..solved.tile_positions.len())
+            .step_by(2)
+            .map(|tile| {
+                let (first, second) = (solved.target_positions[tile], solved.target_positions[tile + 1]);
+                let shape = [(0, 0), (second.0 as i32 - first.0 as i32, second.1 as i32 - first.1 as i32)];
+                (first, PatternDatabase::build_additive(n, &shape))
+            })
+            .collect();
+        for k in 0..=6 {
+            for seed in 0..10 {
+                let state = State::from_input(&Input::scrambled(n, &random_moves(k, seed)));
+                let mut distances = 0;
+                for tile in 1..state.tile_positions.len() {
+                    let distance = state.raw_distance(tile);
+                    assert_eq!(value(&single, &state, state.target_positions[tile]) as u32, distance, "tile {} after {} moves", tile, k);
+                    distances += distance;
+                }
+                let steps: u32 = pairs.iter().map(|(first, database)| value(database, &state, *first) as u32).sum();
+                assert!(distances <= steps && steps <= 2 * k as u32, "{} steps for distances {} after {} moves", steps, distances, k);
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use crate::constructive::lattice_effect;
+use crate::moves::Move;
+use crate::state::State;
+use crate::utils::mix;
+use crate::{Input, Solution, SolverConfig};
+use std::time::Instant;
+
+/// Key of what `moves` do on the unwrapped lattice: the direction of the first move, the parity
+/// of the length, the offset of the blank and every tile moved, with where it came from.
+///
+/// Two sequences with the same key fit the same place of a solution, since moves alternate, and
+/// do the same on any board: the board is the lattice folded onto the torus.
+fn key(moves: &[Move]) -> u64 {
+    let (mut effect, blank) = lattice_effect(moves);
+    effect.sort_unstable();
+    let mut key = mix(moves.first().is_none_or(|m| m.is_clockwise()) as u64 * 2 + moves.len() as u64 % 2);
+    for (i, j) in [blank].into_iter().chain(effect.into_iter().flat_map(|(cell, from)| [cell, from])) {
+        key = mix(key ^ ((i as u32 as u64) << 32) ^ j as u32 as u64);
+    }
+    key
+}
+
+/// The shortest sequence of every effect reached within `length` moves, by `key`.
+///
+/// The empty sequence is stored under both directions, since it fits anywhere.
+pub struct RewriteTable {
+    shortest: rustc_hash::FxHashMap<u64, Vec<Move>>,
+    length: usize,
+}
+
+impl RewriteTable {
+    pub fn build(length: usize) -> Self {
+        fn extend(path: &mut Vec<Move>, length: usize, shortest: &mut rustc_hash::FxHashMap<u64, Vec<Move>>) {
+            let known = shortest.entry(key(path)).or_insert_with(|| path.clone());
+            if known.len() > path.len() {
+                *known = path.clone();
+            }
+            if path.len() == length {
+                return;
+            }
+            for m in Move::ALL {
+                // moves alternate, and a sequence that undoes its last move is never the shortest
+                if path.last().is_some_and(|&last| last.is_clockwise() == m.is_clockwise() || last.inverse() == m) {
+                    continue;
+                }
+                path.push(m);
+                extend(path, length, shortest);
+                path.pop();
+            }
+        }
+        let mut shortest = rustc_hash::FxHashMap::default();
+        extend(&mut vec![], length, &mut shortest);
+        // `key` gives the empty sequence the clockwise direction
+        shortest.insert(key(&[Move::CcwA, Move::CcwA.inverse()]), vec![]);
+        RewriteTable { shortest, length }
+    }
+
+    pub fn len(&self) -> usize {
+        self.shortest.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shortest.is_empty()
+    }
+
+    /// A shorter sequence doing the same as `moves`, if the table has one.
+    pub fn shorter(&self, moves: &[Move]) -> Option<&[Move]> {
+        let shorter = self.shortest.get(&key(moves))?;
+        (shorter.len() < moves.len()).then_some(shorter.as_slice())
+    }
+
+    /// Rewrites `moves`, played from `state`, until no window of up to `length` moves has a
+    /// shorter equivalent. Each rewrite is replayed on the board, and dropped if the boards
+    /// differ, which only a collision of `key` can cause.
+    pub fn simplify(&self, state: &State, moves: &[Move]) -> Vec<Move> {
+        let mut moves = moves.to_vec();
+        loop {
+            let mut state = state.clone();
+            let mut changed = false;
+            let mut i = 0;
+            while i < moves.len() {
+                let rewrite = (2..=self.length.min(moves.len() - i)).rev().find_map(|len| {
+                    let window = &moves[i..i + len];
+                    let shorter = self.shorter(window)?;
+                    let (mut before, mut after) = (state.clone(), state.clone());
+                    window.iter().for_each(|&m| before.apply(m));
+                    shorter.iter().for_each(|&m| after.apply(m));
+                    (before.board == after.board).then(|| (len, shorter.to_vec()))
+                });
+                match rewrite {
+                    Some((len, shorter)) => {
+                        moves.splice(i..i + len, shorter);
+                        changed = true;
+                    }
+                    None => {
+                        state.apply(moves[i]);
+                        i += 1;
+                    }
+                }
+            }
+            // a rewrite can open another one before it, left to the next pass
+            if !changed {
+                return moves;
+            }
+        }
+    }
+}
+
+/// Shortens the moves of `solution` with a `RewriteTable` of sequences up to `length` moves.
+pub fn simplify(input: &Input, solution: Solution, length: usize, config: &SolverConfig) -> Solution {
+    let start = Instant::now();
+    let table = RewriteTable::build(length);
+    let mut state = State::from_input(input);
+    state.set_hash(config.hash);
+    let moves = table.simplify(&state, &solution.moves);
+    if config.verbose >= 1 {
+        eprintln!(
+            "simplify: {} -> {} moves ({} effects within {} moves), {:.2}s",
+            solution.moves.len(),
+            moves.len(),
+            table.len(),
+            length,
+            start.elapsed().as_secs_f64()
+        );
+    }
+    Solution { moves, ..solution }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::random_moves;
+
+    #[test]
+    fn rewrites_keep_the_board() {
+        let table = RewriteTable::build(6);
+        let mut rewrites = 0;
+        for n in [3, 6] {
+            let start = State::from_input(&Input::solved(n));
+            for seed in 0..20 {
+                let moves = random_moves(200, seed);
+                // every rewrite of the table on its own, without the check of `simplify`
+                for window in moves.windows(6).flat_map(|w| (2..=6).map(move |len| &w[..len])) {
+                    let Some(shorter) = table.shorter(window) else {
+                        continue;
+                    };
+                    rewrites += 1;
+                    let (mut before, mut after) = (start.clone(), start.clone());
+                    window.iter().for_each(|&m| before.apply(m));
+                    shorter.iter().for_each(|&m| after.apply(m));
+                    assert!(before.board == after.board, "n = {}: {:?} is not {:?}", n, shorter, window);
+                    assert!(shorter.first().is_none_or(|m| m.is_clockwise() == window[0].is_clockwise()), "{:?} starts the other way", shorter);
+                    assert!(shorter.len() % 2 == window.len() % 2, "{:?} ends the other way", shorter);
+                }
+
+                let simplified = table.simplify(&start, &moves);
+                let (mut before, mut after) = (start.clone(), start.clone());
+                moves.iter().for_each(|&m| before.apply(m));
+                simplified.iter().for_each(|&m| after.apply(m));
+                assert!(before.board == after.board, "n = {}, seed {}: the board changed", n, seed);
+                assert!(simplified.len() <= moves.len(), "n = {}, seed {}: longer", n, seed);
+                let alternate = simplified.windows(2).all(|w| w[0].is_clockwise() != w[1].is_clockwise());
+                assert!(alternate, "n = {}, seed {}: moves do not alternate", n, seed);
+            }
+        }
+        assert!(rewrites > 0, "no window was rewritten");
+    }
+}
@@ -0,0 +1,120 @@
+use crate::evaluator::Weights;
+
+/// What moves a search through the phases of a `Schedule`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PhaseKey {
+    /// the number of moves made so far; phases are in increasing order of `at`
+    Depth,
+    /// the best score of the previous depth; phases are in decreasing order of `at`
+    Score,
+}
+
+/// Evaluator weights in effect from the point `at` of the search on.
+#[derive(Clone, Copy, Debug)]
+pub struct Phase {
+    pub at: f64,
+    pub weights: Weights,
+}
+
+/// Evaluator weights that change during a search. The first phase applies from the start
+/// whatever its `at`; with `interpolate` the weights move linearly from one phase to the next
+/// instead of switching at once.
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    pub key: PhaseKey,
+    pub phases: Vec<Phase>,
+    pub interpolate: bool,
+}
+
+/// Interpolated weights are rounded to this many steps between two phases, so that the leaves
+/// of the search are only rescored a few times.
+const STEPS: f64 = 8.0;
+
+impl Schedule {
+    /// Position in the schedule at key value `value`: the index of the current phase plus the
+    /// fraction of the way to the next one (always 0 without `interpolate`).
+    pub fn position(&self, value: f64) -> f64 {
+        let passed = |at: f64| match self.key {
+            PhaseKey::Depth => value >= at,
+            PhaseKey::Score => value <= at,
+        };
+        let idx = self.phases.iter().rposition(|phase| passed(phase.at)).unwrap_or(0);
+        if !self.interpolate || idx + 1 >= self.phases.len() || !passed(self.phases[idx].at) {
+            return idx as f64;
+        }
+        let (from, to) = (self.phases[idx].at, self.phases[idx + 1].at);
+        let fraction = ((value - from) / (to - from)).clamp(0.0, 1.0);
+        idx as f64 + (fraction * STEPS).floor() / STEPS
+    }
+
+    /// Weights at `position`, see `position`.
+    pub fn weights(&self, position: f64) -> Weights {
+        let idx = (position.floor() as usize).min(self.phases.len() - 1);
+        let fraction = position - idx as f64;
+        let from = self.phases[idx].weights;
+        if fraction == 0.0 {
+            return from;
+        }
+        let to = self.phases[idx + 1].weights;
+        let mix = |a: f64, b: f64| a + (b - a) * fraction;
+        Weights {
+            distance_exponent: mix(from.distance_exponent, to.distance_exponent),
+            mismatch_weight: mix(from.mismatch_weight, to.mismatch_weight),
+            blank_weight: mix(from.blank_weight, to.blank_weight),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weights(mismatch_weight: f64) -> Weights {
+        Weights { distance_exponent: 1.7, mismatch_weight, blank_weight: 0.0 }
+    }
+
+    fn schedule(key: PhaseKey, at: [f64; 2], interpolate: bool) -> Schedule {
+        Schedule { key, phases: vec![Phase { at: at[0], weights: weights(1.0) }, Phase { at: at[1], weights: weights(9.0) }], interpolate }
+    }
+
+    #[test]
+    fn interpolation_ramps_in_steps() {
+        let schedule = schedule(PhaseKey::Depth, [10.0, 90.0], true);
+        // before the first phase, which applies from the start anyway
+        assert_eq!(schedule.position(0.0), 0.0);
+        assert_eq!(schedule.position(10.0), 0.0);
+        // 1/8 of the way from 10 to 90 is 20, and positions only change at whole steps
+        assert_eq!(schedule.position(19.0), 0.0);
+        assert_eq!(schedule.position(20.0), 0.125);
+        assert_eq!(schedule.position(29.0), 0.125);
+        assert_eq!(schedule.position(50.0), 0.5);
+        assert_eq!(schedule.position(89.0), 0.875);
+        // at and beyond the last phase
+        assert_eq!(schedule.position(90.0), 1.0);
+        assert_eq!(schedule.position(1000.0), 1.0);
+
+        assert_eq!(schedule.weights(0.0), weights(1.0));
+        assert_eq!(schedule.weights(0.5), weights(5.0));
+        assert_eq!(schedule.weights(0.875), weights(8.0));
+        assert_eq!(schedule.weights(1.0), weights(9.0));
+        assert_eq!(schedule.weights(schedule.position(1000.0)), weights(9.0));
+    }
+
+    #[test]
+    fn score_schedules_run_downwards() {
+        let schedule = schedule(PhaseKey::Score, [1000.0, 200.0], true);
+        assert_eq!(schedule.position(5000.0), 0.0);
+        assert_eq!(schedule.position(600.0), 0.5);
+        assert_eq!(schedule.position(200.0), 1.0);
+        assert_eq!(schedule.position(0.0), 1.0);
+    }
+
+    #[test]
+    fn without_interpolation_phases_switch_at_once() {
+        let schedule = schedule(PhaseKey::Depth, [0.0, 80.0], false);
+        assert_eq!(schedule.position(79.0), 0.0);
+        assert_eq!(schedule.weights(schedule.position(79.0)), weights(1.0));
+        assert_eq!(schedule.position(80.0), 1.0);
+        assert_eq!(schedule.weights(schedule.position(80.0)), weights(9.0));
+    }
+}
@@ -0,0 +1,117 @@
+use crate::constructive;
+use crate::evaluator::DefaultEvaluator;
+use crate::state::State;
+use crate::{search, Input, Solution, SolverConfig};
+use std::str::FromStr;
+use std::time::Instant;
+
+/// Order in which `solve_staged` places the tiles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StageOrder {
+    /// one stage per ring of targets around the blank's target, outermost first
+    Rings,
+    /// one stage per row of targets, top first
+    Rows,
+}
+
+impl FromStr for StageOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rings" => Ok(StageOrder::Rings),
+            "rows" => Ok(StageOrder::Rows),
+            _ => Err(format!("unknown stage order {:?} (expected rings or rows)", s)),
+        }
+    }
+}
+
+/// Depths a stage may go without improving its best score, per tile of its own, not counting the
+/// stages merged into it. Each depth takes longer than the one before, so a stage held only to
+/// the global `stall_limit` could stall for longer than a search of the whole board takes.
+const STALL_PER_TILE: usize = 2;
+
+/// The tiles of each stage, in the order they are placed. The blank is in none of them.
+pub fn stages(state: &State, order: StageOrder) -> Vec<Vec<usize>> {
+    let center = state.target_positions[0];
+    let key = |i: usize| {
+        let target = state.target_positions[i];
+        match order {
+            StageOrder::Rings => state.n() - state.geometry.distance(target, center) as usize,
+            StageOrder::Rows => target.0,
+        }
+    };
+    let mut tiles = (1..state.tile_positions.len()).collect::<Vec<_>>();
+    tiles.sort_by_key(|&i| (key(i), i));
+    tiles.chunk_by(|&a, &b| key(a) == key(b)).map(|stage| stage.to_vec()).collect()
+}
+
+/// Places the tiles of each stage of `stages` with a beam search of its own, which ends when the
+/// tiles of that stage and the earlier ones are in place; the earlier ones may move in between.
+/// The tiles of later stages only weigh `inactive_weight`, which keeps the blank from wandering
+/// aimlessly among them, and the blank only counts in the last stage.
+///
+/// A stage whose search fails is merged into the next one, which starts again from where the
+/// previous stage ended; the last stage has every tile active, as in `solve`, and if it fails too,
+/// `constructive::finish` places the tiles left from where it ended. The time limit is shared
+/// between the stages in proportion to their number of tiles, and a stage gives up after
+/// `STALL_PER_TILE` depths without improvement per tile of its own, if `stall_limit` does not
+/// stop it first.
+pub fn solve_staged(input: &Input, config: &SolverConfig, order: StageOrder) -> Solution {
+    let start = Instant::now();
+    let mut state = State::from_input(input);
+    state.set_hash(config.hash);
+    state.set_distance_exponent(config.distance_exponent);
+    let stages = stages(&state, order);
+    let mut remaining = state.tile_positions.len() - 1;
+
+    let mut active = vec![false; state.tile_positions.len()];
+    let mut moves = vec![];
+    let mut peak_nodes = 0;
+    // tiles of the stages merged into the current one
+    let mut merged = 0;
+    for (k, stage) in stages.iter().enumerate() {
+        stage.iter().for_each(|&i| active[i] = true);
+        active[0] = k + 1 == stages.len();
+        merged += stage.len();
+        let mut stage_state = state.clone();
+        stage_state.set_active(active.clone());
+        if stage_state.is_solved() {
+            remaining -= merged;
+            merged = 0;
+            continue;
+        }
+        let evaluator = Box::new(DefaultEvaluator { inactive_weight: config.inactive_weight, ..config.default_evaluator() });
+
+        let stage_config = SolverConfig {
+            time_limit: config.time_limit.map(|limit| limit.saturating_sub(start.elapsed()).mul_f64(merged as f64 / remaining as f64)),
+            max_depth: config.max_depth.map(|max_depth| max_depth.saturating_sub(moves.len()).max(1)),
+            stall_limit: Some(config.stall_limit.unwrap_or(usize::MAX).min(STALL_PER_TILE * stage.len())),
+            verbose: config.verbose.saturating_sub(1),
+            ..config.clone()
+        };
+        let solution = search(stage_state, moves.last().copied(), evaluator, &stage_config);
+        peak_nodes = peak_nodes.max(solution.peak_nodes);
+        if !solution.solved {
+            let last = k + 1 == stages.len();
+            if config.verbose >= 1 {
+                let next = if last { "finishing constructively" } else { "merging it into the next one" };
+                eprintln!("stage {}/{}: not solved, {}, {:.2}s", k + 1, stages.len(), next, start.elapsed().as_secs_f64());
+            }
+            if last {
+                moves.extend(solution.moves);
+                let stalled = Solution { moves, solved: false, score: solution.score, peak_nodes, optimal: false };
+                return constructive::finish(input, stalled, config);
+            }
+            continue;
+        }
+        solution.moves.iter().for_each(|&m| state.apply(m));
+        moves.extend(solution.moves);
+        remaining -= merged;
+        merged = 0;
+        if config.verbose >= 1 {
+            eprintln!("stage {}/{}: {} tiles, {} moves in total, {:.2}s", k + 1, stages.len(), stage.len(), moves.len(), start.elapsed().as_secs_f64());
+        }
+    }
+    Solution { moves, solved: true, score: 0, peak_nodes, optimal: false }
+}
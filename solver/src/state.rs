@@ -1,20 +1,62 @@
+use crate::geometry::Geometry;
+use crate::hash::{HashKind, PolynomialHash, StateHash};
 use crate::input::Input;
-use crate::utils::bases;
-use crate::utils::change;
-use crate::utils::hash;
-use crate::utils::BASE;
+use crate::moves::Move;
+use crate::utils::mix;
+use crate::utils::MOD;
+use std::str::FromStr;
+use std::sync::Arc;
 
+/// Which misplaced tile `State::blank_gap` measures the distance of the blank to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlankTarget {
+    /// the misplaced tile nearest to the blank
+    Nearest,
+    /// the nearest of the tiles farthest from their targets, which cost the most
+    Farthest,
+}
+
+impl FromStr for BlankTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(BlankTarget::Nearest),
+            "farthest" => Ok(BlankTarget::Farthest),
+            _ => Err(format!("unknown blank target {:?} (expected nearest or farthest)", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for BlankTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            BlankTarget::Nearest => "nearest",
+            BlankTarget::Farthest => "farthest",
+        })
+    }
+}
+
+#[derive(Clone)]
 pub struct State {
     n: usize,
+    pub geometry: Arc<Geometry>,
     pub board: Vec<Vec<i32>>,
     pub tile_positions: Vec<(usize, usize)>,
     pub target_positions: Vec<(usize, usize)>,
     pub zero_position: (usize, usize),
+    active: Vec<bool>, // tiles that count as misplaced when off target
     mismatch_i: Vec<u64>,
     mismatch_j: Vec<u64>,
     mismatch_k: Vec<u64>, // diagonal, idx: (n - 1) + j - i
-    pub bases: Vec<u64>,
+    mismatch_extents: [(usize, usize); 3], // first and last nonzero index of mismatch_i/j/k
+    misplaced: Vec<Vec<u32>>, // misplaced tiles other than the blank, by distance from their target
+    misplaced_slot: Vec<u32>, // index of each misplaced tile in its `misplaced` list
+    misplaced_count: usize,
+    hasher: Arc<dyn StateHash>,
     pub hash: u64,
+    distance_exponent: f64,
+    weighted_table: Vec<u32>,
     ope_count: i32,
 }
 
@@ -25,76 +67,170 @@ impl State {
             board[i][j] = idx as i32;
         }
         let zero_position = tile_positions[0];
-        let mut mismatch_i = vec![0; 2 * input.n - 1];
-        let mut mismatch_j = vec![0; 2 * input.n - 1];
-        let mut mismatch_k = vec![0; 2 * input.n - 1];
-        for i in 0..2 * input.n - 1 {
-            for j in 0..2 * input.n - 1 {
-                if board[i][j] != -1 {
-                    let num = board[i][j] as usize;
-                    if tile_positions[num] != target_positions[num] {
-                        mismatch_i[i] += 1;
-                        mismatch_j[j] += 1;
-                        mismatch_k[(input.n - 1) + j - i] += 1;
-                    }
-                }
-            }
-        }
 
-        let bases = bases(BASE, (2 * input.n - 1) * (2 * input.n - 1) - input.n * (input.n - 1) + 1);
-        let mut tile_vec = tile_positions.iter().map(|&(i, j)| (i * (2 * input.n - 1) + j) as u64).collect::<Vec<_>>();
-        tile_vec.push(0); // last op
-        let hash = hash(&tile_vec, BASE);
-
-        State {
+        let geometry = Geometry::get(input.n);
+        let weighted_table = geometry.weighted_distances(1.7);
+        let active = vec![true; tile_positions.len()];
+        let hasher = Arc::new(PolynomialHash::new(tile_positions.len() + 2));
+        let mut state = State {
             n: input.n,
+            geometry,
             board,
             tile_positions,
             target_positions,
             zero_position,
-            mismatch_i,
-            mismatch_j,
-            mismatch_k,
-            bases,
-            hash,
+            active,
+            mismatch_i: vec![],
+            mismatch_j: vec![],
+            mismatch_k: vec![],
+            mismatch_extents: [(usize::MAX, 0); 3],
+            misplaced: vec![],
+            misplaced_slot: vec![],
+            misplaced_count: 0,
+            hasher,
+            hash: 0,
+            distance_exponent: 1.7,
+            weighted_table,
             ope_count: 0,
+        };
+        state.count_mismatches();
+        state.hash = state.full_hash();
+        state
+    }
+    pub fn from_input(input: &Input) -> Self {
+        let (tile_positions, target_positions) = input.positions();
+        State::new(input, tile_positions, target_positions)
+    }
+    pub fn n(&self) -> usize {
+        self.n
+    }
+    /// Restricts the score to the tiles `i` with `active[i]`; the others count as placed wherever
+    /// they are, in `mismatch_cost`, `weighted_distance` and `is_solved`.
+    ///
+    /// Inactive tiles other than the blank are also indistinguishable in `hash`, so that states
+    /// differing only in how they are arranged count as duplicates.
+    pub fn set_active(&mut self, active: Vec<bool>) {
+        assert_eq!(active.len(), self.tile_positions.len());
+        let mut hash = self.hash;
+        for (num, &cell) in self.tile_positions.iter().enumerate() {
+            let (key, value) = self.hash_term(num, cell);
+            hash = self.hasher.change(hash, key, value, 0);
         }
+        self.active = active;
+        for (num, &cell) in self.tile_positions.iter().enumerate() {
+            let (key, value) = self.hash_term(num, cell);
+            hash = self.hasher.change(hash, key, 0, value);
+        }
+        self.hash = hash;
+        self.count_mismatches();
     }
-    fn is_valid_coordinates(&self, i: i32, j: i32) -> bool {
-        0 <= i && i < 2 * self.n as i32 - 1 && 0 <= j && j < 2 * self.n as i32 - 1 && i < j + self.n as i32 && j < i + self.n as i32
-    }
-    fn wrap_coordinates(&self, i: i32, j: i32) -> (usize, usize) {
-        let mut wrapped_i = i;
-        let mut wrapped_j = j;
-        let offsets: [(i32, i32); 7] = [
-            (0, 0),
-            (-(self.n as i32) + 1, self.n as i32),
-            (self.n as i32, 2 * self.n as i32 - 1),
-            (2 * self.n as i32 - 1, self.n as i32 - 1),
-            (self.n as i32 - 1, -(self.n as i32)),
-            (-(self.n as i32), -2 * self.n as i32 + 1),
-            (-2 * self.n as i32 + 1, -(self.n as i32) + 1),
-        ];
-        for &(di, dj) in offsets.iter() {
-            if self.is_valid_coordinates(i + di, j + dj) {
-                wrapped_i = i + di;
-                wrapped_j = j + dj;
-                break;
+    /// Switches to the `StateHash` of `kind`; only before the first move, whose direction the
+    /// hash includes.
+    pub fn set_hash(&mut self, kind: HashKind) {
+        assert!(self.ope_count == 0, "the hash can only be switched before the first move");
+        self.hasher = kind.build(self.tile_positions.len() + 2);
+        self.hash = self.full_hash();
+    }
+    /// `hash` computed from scratch, with no last move.
+    fn full_hash(&self) -> u64 {
+        let mut hash = 0;
+        for (num, &cell) in self.tile_positions.iter().enumerate() {
+            let (key, value) = self.hash_term(num, cell);
+            hash = self.hasher.change(hash, key, 0, value);
+        }
+        hash
+    }
+    /// Cells of every tile, the inactive ones (see `set_active`) all marked the same, and the
+    /// direction of the last move; states are equal in the search iff their fingerprints are.
+    pub fn fingerprint(&self, last: Move) -> Box<[u16]> {
+        let mut fingerprint = Vec::with_capacity(self.geometry.cells().len() + 1);
+        for &(i, j) in self.geometry.cells() {
+            let num = self.board[i][j] as usize;
+            fingerprint.push(if num == 0 || self.active[num] { num as u16 } else { u16::MAX });
+        }
+        fingerprint.push(last.is_clockwise() as u16);
+        fingerprint.into_boxed_slice()
+    }
+    pub fn is_active(&self, i: usize) -> bool {
+        self.active[i]
+    }
+    /// Key and value of the slot of tile `num` at `(i, j)` in `hash`. Inactive tiles share one
+    /// key with a scrambled value, so only the set of cells they occupy matters.
+    fn hash_term(&self, num: usize, (i, j): (usize, usize)) -> (usize, u64) {
+        let cell = (i * (2 * self.n - 1) + j) as u64;
+        if num == 0 || self.active[num] {
+            (num, cell)
+        } else {
+            (self.tile_positions.len() + 1, mix(cell) % MOD)
+        }
+    }
+    /// Key of the slot of the direction of the last move in `hash`: 0 before the first move, 1
+    /// after a clockwise one and 2 after an anticlockwise one.
+    fn last_move_key(&self) -> usize {
+        self.tile_positions.len()
+    }
+    fn is_misplaced_at(&self, i: usize, cell: (usize, usize)) -> bool {
+        self.active[i] && self.target_positions[i] != cell
+    }
+    fn count_mismatches(&mut self) {
+        let size = 2 * self.n - 1;
+        self.mismatch_i = vec![0; size];
+        self.mismatch_j = vec![0; size];
+        self.mismatch_k = vec![0; size];
+        for (num, &(i, j)) in self.tile_positions.iter().enumerate() {
+            if self.is_misplaced_at(num, (i, j)) {
+                self.mismatch_i[i] += 1;
+                self.mismatch_j[j] += 1;
+                self.mismatch_k[(self.n - 1) + j - i] += 1;
             }
         }
-        (wrapped_i as usize, wrapped_j as usize)
+        self.mismatch_extents = [extent(&self.mismatch_i), extent(&self.mismatch_j), extent(&self.mismatch_k)];
+
+        self.misplaced = vec![vec![]; self.geometry.max_distance() as usize + 1];
+        self.misplaced_slot = vec![0; self.tile_positions.len()];
+        self.misplaced_count = 0;
+        for num in 1..self.tile_positions.len() {
+            self.add_misplaced(num);
+        }
+    }
+    /// Adds tile `num` to `misplaced` if it is misplaced; `remove_misplaced` undoes it.
+    fn add_misplaced(&mut self, num: usize) {
+        let cell = self.tile_positions[num];
+        if num == 0 || !self.is_misplaced_at(num, cell) {
+            return;
+        }
+        let list = &mut self.misplaced[self.geometry.distance(cell, self.target_positions[num]) as usize];
+        self.misplaced_slot[num] = list.len() as u32;
+        list.push(num as u32);
+        self.misplaced_count += 1;
+    }
+    fn remove_misplaced(&mut self, num: usize) {
+        let cell = self.tile_positions[num];
+        if num == 0 || !self.is_misplaced_at(num, cell) {
+            return;
+        }
+        let list = &mut self.misplaced[self.geometry.distance(cell, self.target_positions[num]) as usize];
+        let slot = self.misplaced_slot[num] as usize;
+        list.swap_remove(slot);
+        if let Some(&last) = list.get(slot) {
+            self.misplaced_slot[last as usize] = slot as u32;
+        }
+        self.misplaced_count -= 1;
     }
     fn rotate_tiles(&mut self, a_i: usize, a_j: usize, b_i: usize, b_j: usize, c_i: usize, c_j: usize) {
-        let hash = change(self.hash, &self.bases, self.board[a_i][a_j] as usize, (a_i * (2 * self.n - 1) + a_j) as u64, 0);
-        let hash = change(hash, &self.bases, self.board[b_i][b_j] as usize, (b_i * (2 * self.n - 1) + b_j) as u64, 0);
-        let hash = change(hash, &self.bases, self.board[c_i][c_j] as usize, (c_i * (2 * self.n - 1) + c_j) as u64, 0);
+        let mut hash = self.hash;
+        for &(i, j) in &[(a_i, a_j), (b_i, b_j), (c_i, c_j)] {
+            let (key, value) = self.hash_term(self.board[i][j] as usize, (i, j));
+            hash = self.hasher.change(hash, key, value, 0);
+        }
         for &(i, j) in &[(a_i, a_j), (b_i, b_j), (c_i, c_j)] {
             let num = self.board[i][j] as usize;
-            if self.target_positions[num] != self.tile_positions[num] {
-                self.mismatch_i[i] -= 1;
-                self.mismatch_j[j] -= 1;
-                self.mismatch_k[(self.n - 1) + j - i] -= 1;
+            if self.is_misplaced_at(num, (i, j)) {
+                remove_mismatch(&mut self.mismatch_i, &mut self.mismatch_extents[0], i);
+                remove_mismatch(&mut self.mismatch_j, &mut self.mismatch_extents[1], j);
+                remove_mismatch(&mut self.mismatch_k, &mut self.mismatch_extents[2], (self.n - 1) + j - i);
             }
+            self.remove_misplaced(num);
         }
         (self.board[a_i][a_j], self.board[b_i][b_j], self.board[c_i][c_j]) = (self.board[c_i][c_j], self.board[a_i][a_j], self.board[b_i][b_j]);
         self.tile_positions[self.board[b_i][b_j] as usize] = (b_i, b_j);
@@ -102,98 +238,166 @@ impl State {
         self.tile_positions[self.board[a_i][a_j] as usize] = (a_i, a_j);
         for &(i, j) in &[(a_i, a_j), (b_i, b_j), (c_i, c_j)] {
             let num = self.board[i][j] as usize;
-            if self.target_positions[num] != self.tile_positions[num] {
-                self.mismatch_i[i] += 1;
-                self.mismatch_j[j] += 1;
-                self.mismatch_k[(self.n - 1) + j - i] += 1;
+            if self.is_misplaced_at(num, (i, j)) {
+                add_mismatch(&mut self.mismatch_i, &mut self.mismatch_extents[0], i);
+                add_mismatch(&mut self.mismatch_j, &mut self.mismatch_extents[1], j);
+                add_mismatch(&mut self.mismatch_k, &mut self.mismatch_extents[2], (self.n - 1) + j - i);
             }
+            self.add_misplaced(num);
+        }
+        for &(i, j) in &[(a_i, a_j), (b_i, b_j), (c_i, c_j)] {
+            let (key, value) = self.hash_term(self.board[i][j] as usize, (i, j));
+            hash = self.hasher.change(hash, key, 0, value);
         }
-        let hash = change(hash, &self.bases, self.board[a_i][a_j] as usize, 0, (a_i * (2 * self.n - 1) + a_j) as u64);
-        let hash = change(hash, &self.bases, self.board[b_i][b_j] as usize, 0, (b_i * (2 * self.n - 1) + b_j) as u64);
-        let hash = change(hash, &self.bases, self.board[c_i][c_j] as usize, 0, (c_i * (2 * self.n - 1) + c_j) as u64);
         self.hash = hash;
     }
-    pub fn apply(&mut self, m: char) {
+    pub fn apply(&mut self, m: Move) {
         let (a_i, a_j) = self.tile_positions[0];
-        let ((b_i, b_j), (c_i, c_j)) = match m {
-            '1' => (self.wrap_coordinates(a_i as i32 - 1, a_j as i32), self.wrap_coordinates(a_i as i32, a_j as i32 + 1)),
-            '2' => (self.wrap_coordinates(a_i as i32, a_j as i32 + 1), self.wrap_coordinates(a_i as i32 + 1, a_j as i32 + 1)),
-            '3' => (self.wrap_coordinates(a_i as i32 + 1, a_j as i32 + 1), self.wrap_coordinates(a_i as i32 + 1, a_j as i32)),
-            '4' => (self.wrap_coordinates(a_i as i32 + 1, a_j as i32), self.wrap_coordinates(a_i as i32, a_j as i32 - 1)),
-            '5' => (self.wrap_coordinates(a_i as i32, a_j as i32 - 1), self.wrap_coordinates(a_i as i32 - 1, a_j as i32 - 1)),
-            '6' => (self.wrap_coordinates(a_i as i32 - 1, a_j as i32 - 1), self.wrap_coordinates(a_i as i32 - 1, a_j as i32)),
-            'A' => (self.wrap_coordinates(a_i as i32, a_j as i32 + 1), self.wrap_coordinates(a_i as i32 - 1, a_j as i32)),
-            'B' => (self.wrap_coordinates(a_i as i32 + 1, a_j as i32 + 1), self.wrap_coordinates(a_i as i32, a_j as i32 + 1)),
-            'C' => (self.wrap_coordinates(a_i as i32 + 1, a_j as i32), self.wrap_coordinates(a_i as i32 + 1, a_j as i32 + 1)),
-            'D' => (self.wrap_coordinates(a_i as i32, a_j as i32 - 1), self.wrap_coordinates(a_i as i32 + 1, a_j as i32)),
-            'E' => (self.wrap_coordinates(a_i as i32 - 1, a_j as i32 - 1), self.wrap_coordinates(a_i as i32, a_j as i32 - 1)),
-            'F' => (self.wrap_coordinates(a_i as i32 - 1, a_j as i32), self.wrap_coordinates(a_i as i32 - 1, a_j as i32 - 1)),
-            _ => unreachable!(),
-        };
+        let ((b_i, b_j), (c_i, c_j)) = self.geometry.rotated_cells((a_i, a_j), m);
         self.rotate_tiles(a_i, a_j, b_i, b_j, c_i, c_j);
-        let is_clockwise = match m {
-            '1' | '2' | '3' | '4' | '5' | '6' => true,
-            'A' | 'B' | 'C' | 'D' | 'E' | 'F' => false,
-            _ => unreachable!(),
-        };
+        let is_clockwise = m.is_clockwise();
         let mut hash = self.hash;
         if self.ope_count != 0 {
             if is_clockwise {
-                hash = change(hash, &self.bases, self.bases.len() - 1, 2, 0);
+                hash = self.hasher.change(hash, self.last_move_key(), 2, 0);
             } else {
-                hash = change(hash, &self.bases, self.bases.len() - 1, 1, 0);
+                hash = self.hasher.change(hash, self.last_move_key(), 1, 0);
             }
         }
         if is_clockwise {
-            hash = change(hash, &self.bases, self.bases.len() - 1, 0, 1);
+            hash = self.hasher.change(hash, self.last_move_key(), 0, 1);
         } else {
-            hash = change(hash, &self.bases, self.bases.len() - 1, 0, 2);
+            hash = self.hasher.change(hash, self.last_move_key(), 0, 2);
         }
         self.hash = hash;
         self.ope_count += 1;
         self.zero_position = self.tile_positions[0];
     }
-    pub fn revert(&mut self, m: char) {
+    pub fn revert(&mut self, m: Move) {
         let (a_i, a_j) = self.tile_positions[0];
-        let ((b_i, b_j), (c_i, c_j)) = match m {
-            'C' => (self.wrap_coordinates(a_i as i32 - 1, a_j as i32), self.wrap_coordinates(a_i as i32, a_j as i32 + 1)),
-            'D' => (self.wrap_coordinates(a_i as i32, a_j as i32 + 1), self.wrap_coordinates(a_i as i32 + 1, a_j as i32 + 1)),
-            'E' => (self.wrap_coordinates(a_i as i32 + 1, a_j as i32 + 1), self.wrap_coordinates(a_i as i32 + 1, a_j as i32)),
-            'F' => (self.wrap_coordinates(a_i as i32 + 1, a_j as i32), self.wrap_coordinates(a_i as i32, a_j as i32 - 1)),
-            'A' => (self.wrap_coordinates(a_i as i32, a_j as i32 - 1), self.wrap_coordinates(a_i as i32 - 1, a_j as i32 - 1)),
-            'B' => (self.wrap_coordinates(a_i as i32 - 1, a_j as i32 - 1), self.wrap_coordinates(a_i as i32 - 1, a_j as i32)),
-            '5' => (self.wrap_coordinates(a_i as i32, a_j as i32 + 1), self.wrap_coordinates(a_i as i32 - 1, a_j as i32)),
-            '6' => (self.wrap_coordinates(a_i as i32 + 1, a_j as i32 + 1), self.wrap_coordinates(a_i as i32, a_j as i32 + 1)),
-            '1' => (self.wrap_coordinates(a_i as i32 + 1, a_j as i32), self.wrap_coordinates(a_i as i32 + 1, a_j as i32 + 1)),
-            '2' => (self.wrap_coordinates(a_i as i32, a_j as i32 - 1), self.wrap_coordinates(a_i as i32 + 1, a_j as i32)),
-            '3' => (self.wrap_coordinates(a_i as i32 - 1, a_j as i32 - 1), self.wrap_coordinates(a_i as i32, a_j as i32 - 1)),
-            '4' => (self.wrap_coordinates(a_i as i32 - 1, a_j as i32), self.wrap_coordinates(a_i as i32 - 1, a_j as i32 - 1)),
-            _ => unreachable!(),
-        };
+        let ((b_i, b_j), (c_i, c_j)) = self.geometry.rotated_cells((a_i, a_j), m.inverse());
         self.rotate_tiles(a_i, a_j, b_i, b_j, c_i, c_j);
-        let is_clockwise = match m {
-            '1' | '2' | '3' | '4' | '5' | '6' => true,
-            'A' | 'B' | 'C' | 'D' | 'E' | 'F' => false,
-            _ => unreachable!(),
-        };
+        let is_clockwise = m.is_clockwise();
         self.ope_count -= 1;
         let mut hash = self.hash;
         if is_clockwise {
-            hash = change(hash, &self.bases, self.bases.len() - 1, 1, 0);
+            hash = self.hasher.change(hash, self.last_move_key(), 1, 0);
         } else {
-            hash = change(hash, &self.bases, self.bases.len() - 1, 2, 0);
+            hash = self.hasher.change(hash, self.last_move_key(), 2, 0);
         }
         if self.ope_count != 0 {
             if is_clockwise {
-                hash = change(hash, &self.bases, self.bases.len() - 1, 0, 2);
+                hash = self.hasher.change(hash, self.last_move_key(), 0, 2);
             } else {
-                hash = change(hash, &self.bases, self.bases.len() - 1, 0, 1);
+                hash = self.hasher.change(hash, self.last_move_key(), 0, 1);
             }
         }
         self.hash = hash;
         self.zero_position = self.tile_positions[0];
     }
+    /// Sum of the spans of the rows, columns and diagonals that hold a misplaced tile.
     pub fn mismatch_cost(&self) -> u32 {
+        let cost = self.mismatch_extents.iter().map(|&(left, right)| if left == usize::MAX { 0 } else { right - left }).sum::<usize>() as u32;
+        debug_assert_eq!(cost, self.mismatch_cost_full());
+        cost
+    }
+    /// `mismatch_cost` after `m`, without applying it.
+    pub fn mismatch_cost_after(&self, m: Move) -> u32 {
+        self.mismatch_extents_after(m).iter().map(|&(left, right)| if left == usize::MAX { 0 } else { right - left }).sum::<usize>() as u32
+    }
+    /// Span of the misplaced tiles along the rows, columns and diagonals; they add up to
+    /// `mismatch_cost`.
+    pub fn mismatch_spans(&self) -> [u32; 3] {
+        self.mismatch_extents.map(|(left, right)| if left == usize::MAX { 0 } else { (right - left) as u32 })
+    }
+    /// `mismatch_spans` after `m`, without applying it.
+    pub fn mismatch_spans_after(&self, m: Move) -> [u32; 3] {
+        self.mismatch_extents_after(m).map(|(left, right)| if left == usize::MAX { 0 } else { (right - left) as u32 })
+    }
+    pub fn is_solved(&self) -> bool {
+        self.mismatch_extents[0].0 == usize::MAX
+    }
+    /// `is_solved` after `m`, without applying it.
+    pub fn is_solved_after(&self, m: Move) -> bool {
+        self.mismatch_extents_after(m)[0].0 == usize::MAX
+    }
+    fn mismatch_extents_after(&self, m: Move) -> [(usize, usize); 3] {
+        let a = self.tile_positions[0];
+        let (b, c) = self.geometry.rotated_cells(a, m);
+        let x = self.board[b.0][b.1] as usize;
+        let y = self.board[c.0][c.1] as usize;
+        let mut changes = [[(0, 0); 3]; 3];
+        for (idx, &(cell, old, new)) in [(a, 0, y), (b, x, 0), (c, y, x)].iter().enumerate() {
+            let d = self.is_misplaced_at(new, cell) as i64 - self.is_misplaced_at(old, cell) as i64;
+            changes[0][idx] = (cell.0, d);
+            changes[1][idx] = (cell.1, d);
+            changes[2][idx] = ((self.n - 1) + cell.1 - cell.0, d);
+        }
+        let mut extents = [(usize::MAX, 0); 3];
+        for (axis, count) in [&self.mismatch_i, &self.mismatch_j, &self.mismatch_k].into_iter().enumerate() {
+            extents[axis] = extent_after(count, self.mismatch_extents[axis], &changes[axis]);
+        }
+        extents
+    }
+    /// Torus distance from the blank to the misplaced tile picked by `target`, 0 if there is
+    /// none. Blank and tiles are both counted as cells, so an adjacent tile is at distance 1.
+    pub fn blank_gap(&self, target: BlankTarget) -> u32 {
+        self.blank_gap_with(target, self.zero_position, &[])
+    }
+    /// `blank_gap` after `m`, without applying it.
+    pub fn blank_gap_after(&self, target: BlankTarget, m: Move) -> u32 {
+        let a = self.tile_positions[0];
+        let (b, c) = self.geometry.rotated_cells(a, m);
+        let x = self.board[b.0][b.1] as usize;
+        let y = self.board[c.0][c.1] as usize;
+        self.blank_gap_with(target, b, &[(x, c), (y, a)])
+    }
+    /// `blank_gap` with the blank at `blank` and the tiles of `moved` at the given cells.
+    fn blank_gap_with(&self, target: BlankTarget, blank: (usize, usize), moved: &[(usize, (usize, usize))]) -> u32 {
+        let position = |num: usize| moved.iter().find(|&&(t, _)| t == num).map_or(self.tile_positions[num], |&(_, cell)| cell);
+        // distance from the target of a misplaced tile after the move, 0 for a placed one
+        let distance = |num: usize| {
+            let cell = position(num);
+            if num == 0 || !self.is_misplaced_at(num, cell) {
+                return 0;
+            }
+            self.geometry.distance(cell, self.target_positions[num])
+        };
+        let is_moved = |num: usize| moved.iter().any(|&(t, _)| t == num);
+        let listed = |d: usize| self.misplaced[d].iter().map(|&t| t as usize).filter(move |&t| !is_moved(t));
+        let gap = |num: usize| self.geometry.distance(blank, position(num));
+
+        match target {
+            // scanning the board outwards finds a tile in about cells / count steps, which beats
+            // going through the list once there are more than sqrt(cells) of them
+            BlankTarget::Nearest if self.misplaced_count * self.misplaced_count > self.geometry.cells().len() => {
+                let tile_at = |cell: (usize, usize)| moved.iter().find(|&&(_, c)| c == cell).map_or(self.board[cell.0][cell.1] as usize, |&(t, _)| t);
+                for &offset in self.geometry.offsets_by_distance() {
+                    let cell = self.geometry.translate(blank, offset);
+                    if cell == blank {
+                        continue;
+                    }
+                    let num = tile_at(cell);
+                    if num != 0 && self.is_misplaced_at(num, cell) {
+                        return self.geometry.distance(blank, cell);
+                    }
+                }
+                0
+            }
+            BlankTarget::Nearest => {
+                let tiles = (1..self.misplaced.len()).flat_map(listed);
+                let moved_tiles = moved.iter().map(|&(t, _)| t).filter(|&t| distance(t) > 0);
+                tiles.chain(moved_tiles).map(gap).min().unwrap_or(0)
+            }
+            BlankTarget::Farthest => {
+                let top = (1..self.misplaced.len()).rev().find(|&d| listed(d).next().is_some()).unwrap_or(0);
+                let top = moved.iter().map(|&(t, _)| distance(t) as usize).fold(top, usize::max);
+                let moved_tiles = moved.iter().map(|&(t, _)| t).filter(|&t| distance(t) > 0 && distance(t) as usize == top);
+                listed(top).chain(moved_tiles).map(gap).min().unwrap_or(0)
+            }
+        }
+    }
+    fn mismatch_cost_full(&self) -> u32 {
         let mut left_i = usize::MAX;
         let mut right_i = 0;
         let mut left_j = usize::MAX;
@@ -229,51 +433,157 @@ impl State {
         let right_k = if right_k == 0 { 0 } else { right_k };
         (right_i - left_i + right_j - left_j + right_k - left_k) as u32
     }
-    pub fn distance(&self, i: i32, j: i32, i2: i32, j2: i32) -> u32 {
-        if i < i2 && j < j2 {
-            (i2 - i).max(j2 - j) as u32
-        } else if i > i2 && j > j2 {
-            (i - i2).max(j - j2) as u32
-        } else {
-            (i - i2).unsigned_abs() + (j - j2).unsigned_abs()
-        }
-    }
-    pub fn surrounding(&self, i: usize) -> Vec<usize> {
+    /// Tile `i` followed by its six neighbors.
+    pub fn surrounding(&self, i: usize) -> [usize; 7] {
         let (now_i, now_j) = self.tile_positions[i];
-        let mut result = vec![];
-        for &(di, dj) in &[(0, 0), (0, 1), (1, 1), (1, 0), (0, -1), (-1, -1), (-1, 0)] {
-            let (wrapped_i, wrapped_j) = self.wrap_coordinates(now_i as i32 + di, now_j as i32 + dj);
-            result.push(self.board[wrapped_i][wrapped_j] as usize);
+        let mut result = [self.board[now_i][now_j] as usize; 7];
+        for (d, &(wrapped_i, wrapped_j)) in self.geometry.neighbors((now_i, now_j)).iter().enumerate() {
+            result[d + 1] = self.board[wrapped_i][wrapped_j] as usize;
         }
         result
     }
     pub fn raw_distance(&self, i: usize) -> u32 {
-        let (now_i, now_j) = self.tile_positions[i];
-        let (target_i, target_j) = self.target_positions[i];
-        let target_i = target_i as i32;
-        let target_j = target_j as i32;
-        let offsets: [(i32, i32); 7] = [
-            (0, 0),
-            (-(self.n as i32) + 1, self.n as i32),
-            (self.n as i32, 2 * self.n as i32 - 1),
-            (2 * self.n as i32 - 1, self.n as i32 - 1),
-            (self.n as i32 - 1, -(self.n as i32)),
-            (-(self.n as i32), -2 * self.n as i32 + 1),
-            (-2 * self.n as i32 + 1, -(self.n as i32) + 1),
-        ];
-        let mut min_distance = std::u32::MAX;
-        for &(di, dj) in offsets.iter() {
-            let wrapped_i = now_i as i32 + di;
-            let wrapped_j = now_j as i32 + dj;
-            let distance = self.distance(wrapped_i, wrapped_j, target_i, target_j);
-            min_distance = min_distance.min(distance);
-        }
-        min_distance
+        self.geometry.distance(self.tile_positions[i], self.target_positions[i])
     }
     pub fn weighted_distance(&self, i: usize) -> u32 {
+        self.weighted_distance_at(i, self.tile_positions[i])
+    }
+    /// `weighted_distance` of tile `i` if it were at `cell`.
+    pub fn weighted_distance_at(&self, i: usize, cell: (usize, usize)) -> u32 {
+        if !self.active[i] {
+            return 0;
+        }
+        self.unmasked_weighted_distance_at(i, cell)
+    }
+    /// `weighted_distance_at` whether tile `i` is active or not.
+    pub fn unmasked_weighted_distance_at(&self, i: usize, cell: (usize, usize)) -> u32 {
         if i == 0 {
             return 0;
         }
-        (self.raw_distance(i) as f64).powf(1.7).round() as u32
+        self.weighted_table[self.geometry.distance(cell, self.target_positions[i]) as usize]
+    }
+    pub fn distance_exponent(&self) -> f64 {
+        self.distance_exponent
+    }
+    pub fn set_distance_exponent(&mut self, exponent: f64) {
+        self.distance_exponent = exponent;
+        self.weighted_table = self.geometry.weighted_distances(exponent);
+    }
+}
+
+/// First and last index with a nonzero count, `(usize::MAX, 0)` if there is none.
+fn extent(count: &[u64]) -> (usize, usize) {
+    match (count.iter().position(|&c| c > 0), count.iter().rposition(|&c| c > 0)) {
+        (Some(left), Some(right)) => (left, right),
+        _ => (usize::MAX, 0),
+    }
+}
+
+/// `extent` of `count` once every `(idx, d)` of `changes` is added to it.
+fn extent_after(count: &[u64], extent: (usize, usize), changes: &[(usize, i64); 3]) -> (usize, usize) {
+    let after = |idx: usize| count[idx] as i64 + changes.iter().filter(|c| c.0 == idx).map(|c| c.1).sum::<i64>();
+    let (mut left, mut right) = extent;
+    for &(idx, d) in changes.iter() {
+        if d > 0 && after(idx) > 0 {
+            left = left.min(idx);
+            right = right.max(idx);
+        }
+    }
+    if left == usize::MAX {
+        return extent;
+    }
+    while left <= right && after(left) == 0 {
+        left += 1;
+    }
+    if left > right {
+        return (usize::MAX, 0);
+    }
+    while after(right) == 0 {
+        right -= 1;
+    }
+    (left, right)
+}
+
+fn add_mismatch(count: &mut [u64], extent: &mut (usize, usize), idx: usize) {
+    count[idx] += 1;
+    if extent.0 == usize::MAX {
+        *extent = (idx, idx);
+    } else {
+        extent.0 = extent.0.min(idx);
+        extent.1 = extent.1.max(idx);
+    }
+}
+
+fn remove_mismatch(count: &mut [u64], extent: &mut (usize, usize), idx: usize) {
+    count[idx] -= 1;
+    if count[idx] != 0 {
+        return;
+    }
+    if extent.0 == extent.1 {
+        *extent = (usize::MAX, 0);
+    } else if idx == extent.0 {
+        while count[extent.0] == 0 {
+            extent.0 += 1;
+        }
+    } else if idx == extent.1 {
+        while count[extent.1] == 0 {
+            extent.1 -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stages::{stages, StageOrder};
+    use crate::utils::random_moves;
+
+    #[test]
+    fn every_move_is_undone() {
+        for n in [2, 3, 5] {
+            for seed in 0..5 {
+                let state = State::from_input(&Input::scrambled(n, &random_moves(30, seed)));
+                let key = |state: &State| (state.board.clone(), state.tile_positions.clone(), state.zero_position, state.mismatch_cost());
+                for m in Move::ALL {
+                    let mut moved = state.clone();
+                    moved.apply(m);
+                    assert_ne!(moved.board, state.board, "n = {}, {:?}: the board did not change", n, m);
+                    let mut reverted = moved.clone();
+                    reverted.revert(m);
+                    assert!(key(&reverted) == key(&state) && reverted.hash == state.hash, "n = {}, {:?}: revert", n, m);
+                    moved.apply(m.inverse());
+                    assert!(key(&moved) == key(&state), "n = {}, {:?}: apply of {:?}", n, m, m.inverse());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn blank_gap_after_matches_applying() {
+        for n in [3, 5] {
+            let solved = State::from_input(&Input::solved(n));
+            // the mask of the first stage, as `stages::solve_staged` sets it
+            let mut first_stage = vec![false; solved.tile_positions.len()];
+            stages(&solved, StageOrder::Rings)[0].iter().for_each(|&i| first_stage[i] = true);
+            for mask in [None, Some(first_stage)] {
+                for seed in 0..5 {
+                    let mut state = State::from_input(&Input::scrambled(n, &random_moves(30, seed)));
+                    if let Some(mask) = &mask {
+                        state.set_active(mask.clone());
+                    }
+                    for walk in random_moves(200, seed + 100) {
+                        for target in [BlankTarget::Nearest, BlankTarget::Farthest] {
+                            for m in Move::ALL {
+                                let predicted = state.blank_gap_after(target, m);
+                                state.apply(m);
+                                assert_eq!(predicted, state.blank_gap(target), "n = {}, {:?}, {:?}, masked: {}", n, target, m, mask.is_some());
+                                state.revert(m);
+                            }
+                        }
+                        state.apply(walk);
+                    }
+                }
+            }
+        }
     }
 }
@@ -1,3 +1,5 @@
+use crate::moves::Move;
+
 const MASK30: u64 = (1 << 30) - 1;
 const MASK31: u64 = (1 << 31) - 1;
 pub const MOD: u64 = (1 << 61) - 1;
@@ -43,9 +45,35 @@ pub fn bases(base: u64, n: usize) -> Vec<u64> {
 
 pub fn change(hash: u64, bases: &[u64], i: usize, old: u64, new: u64) -> u64 {
     assert!(i < bases.len());
+    change_by(hash, bases[i], old, new)
+}
+
+/// `change` with the base given directly.
+pub fn change_by(hash: u64, base: u64, old: u64, new: u64) -> u64 {
     let mut diff = MOD + new - old;
     if diff >= MOD {
         diff -= MOD;
     }
-    modulo(hash + mul(bases[i], diff))
+    modulo(hash + mul(base, diff))
+}
+
+/// splitmix64 finalizer
+pub fn mix(x: u64) -> u64 {
+    let x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    let x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// `k` moves of alternating directions drawn from `seed`, the first of either direction.
+pub fn random_moves(k: usize, seed: u64) -> Vec<Move> {
+    let mut x = mix(seed);
+    let mut clockwise = x.is_multiple_of(2);
+    (0..k)
+        .map(|_| {
+            x = mix(x);
+            let m = Move::from_index(x as usize % 6 + if clockwise { 0 } else { 6 });
+            clockwise = !clockwise;
+            m
+        })
+        .collect()
 }
@@ -0,0 +1,19 @@
+mod common;
+
+use common::{assert_valid_solution, read};
+use solver::stages::StageOrder;
+use solver::state::BlankTarget;
+use solver::{solve, SolverConfig};
+
+#[test]
+fn every_approach_solves() {
+    let input = read("in3.txt");
+    for approach in [BlankTarget::Nearest, BlankTarget::Farthest] {
+        // on its own, and within stages, where only the tiles of the current one draw the blank
+        for order in [None, Some(StageOrder::Rings)] {
+            let config = SolverConfig { approach, approach_weight: 1.0, stages: order, beam_width: 200, stall_limit: Some(100), ..SolverConfig::default() };
+            let solution = solve(&input, &config);
+            assert_valid_solution(&input, &solution, &format!("{:?}, {:?}", approach, order));
+        }
+    }
+}
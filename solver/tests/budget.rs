@@ -0,0 +1,19 @@
+mod common;
+
+use common::{assert_valid_solution, read};
+use solver::{solve, SolverConfig};
+use std::time::{Duration, Instant};
+
+#[test]
+fn time_limit_is_kept() {
+    for (name, limit) in [("in8.txt", 1.0), ("in12.txt", 2.0)] {
+        let input = read(name);
+        let config = SolverConfig { time_limit: Some(Duration::from_secs_f64(limit)), ..SolverConfig::default() };
+        let start = Instant::now();
+        let solution = solve(&input, &config);
+        let elapsed = start.elapsed().as_secs_f64();
+        // the last depth and the constructive finish come after the limit
+        assert!(elapsed <= limit + 0.5, "{}: {:.2}s for a limit of {}s", name, elapsed, limit);
+        assert_valid_solution(&input, &solution, name);
+    }
+}
@@ -0,0 +1,21 @@
+// every test crate includes this module, and not all of them use all of it
+#![allow(dead_code)]
+
+use solver::input::parse_input;
+use solver::{Input, Solution, State};
+
+/// The instance `input/<name>`.
+pub fn read(name: &str) -> Input {
+    let path = format!("{}/input/{}", env!("CARGO_MANIFEST_DIR"), name);
+    parse_input(&std::fs::read_to_string(path).unwrap())
+}
+
+/// Checks that `solution` is marked solved, that its moves alternate direction, as a submission
+/// must, and that they solve `input`.
+pub fn assert_valid_solution(input: &Input, solution: &Solution, context: &str) {
+    assert!(solution.solved, "{}: not solved", context);
+    assert!(solution.moves.windows(2).all(|w| w[0].is_clockwise() != w[1].is_clockwise()), "{}: moves do not alternate", context);
+    let mut state = State::from_input(input);
+    solution.moves.iter().for_each(|&m| state.apply(m));
+    assert!(state.is_solved(), "{}: the moves do not solve the board", context);
+}
@@ -0,0 +1,17 @@
+mod common;
+
+use common::assert_valid_solution;
+use solver::utils::random_moves;
+use solver::{solve, Input, SolverConfig};
+use std::time::Duration;
+
+#[test]
+fn handoff_finishes_with_a_valid_solution() {
+    for (n, k) in [(3, 10), (4, 30), (5, 100)] {
+        let input = Input::scrambled(n, &random_moves(k, n as u64));
+        // hands off at the first depth, with a perimeter cut short by the time limit
+        let config = SolverConfig { endgame: Some(i64::MAX), time_limit: Some(Duration::from_secs(2)), ..SolverConfig::default() };
+        let solution = solve(&input, &config);
+        assert_valid_solution(&input, &solution, &format!("n = {}", n));
+    }
+}
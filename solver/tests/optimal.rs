@@ -0,0 +1,39 @@
+mod common;
+
+use common::{assert_valid_solution, read};
+use solver::bound::lower_bound;
+use solver::ida::search_optimal;
+use solver::state::State;
+use solver::utils::random_moves;
+use solver::{solve, Input, SolverConfig};
+
+#[test]
+fn short_scrambles_are_solved_optimally() {
+    let config = SolverConfig { optimal: true, ..SolverConfig::default() };
+    for n in [3, 4] {
+        for k in 0..=6 {
+            for seed in 0..5 {
+                let scramble = random_moves(k, seed);
+                let input = Input::scrambled(n, &scramble);
+                let solution = solve(&input, &config);
+                assert_valid_solution(&input, &solution, &format!("n = {}, {:?}", n, scramble));
+                assert!(solution.optimal && solution.moves.len() <= k, "n = {}, {:?}: {} moves", n, scramble, solution.moves.len());
+            }
+        }
+    }
+}
+
+#[test]
+fn full_instance_is_bounded_and_no_longer_than_the_beam() {
+    let input = read("in3.txt");
+    let config = SolverConfig { optimal: true, node_limit: Some(200_000), ..SolverConfig::default() };
+    let beam = solve(&input, &SolverConfig { optimal: false, ..config.clone() });
+    let (solution, bound) = search_optimal(&input, &config);
+    assert_valid_solution(&input, &solution, "in3");
+    let len = solution.moves.len();
+    assert!(len <= beam.moves.len(), "{} moves, the beam search found {}", len, beam.moves.len());
+    // out3.txt solves it in 27 moves
+    let start = lower_bound(&State::from_input(&input));
+    assert!(start <= bound && bound <= 27 && bound as usize <= len, "bound {}, start {}, {} moves", bound, start, len);
+    assert_eq!(solution.optimal, bound as usize == len);
+}
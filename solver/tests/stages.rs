@@ -0,0 +1,38 @@
+mod common;
+
+use common::{assert_valid_solution, read};
+use solver::stages::StageOrder;
+use solver::{solve, SolverConfig};
+use std::time::Instant;
+
+#[test]
+fn every_order_solves() {
+    let input = read("in3.txt");
+    for order in [StageOrder::Rings, StageOrder::Rows] {
+        // also at a width narrow enough for the stages to stall, which the constructive finish
+        // covers
+        for beam_width in [5, 200] {
+            let config = SolverConfig { stages: Some(order), beam_width, ..SolverConfig::default() };
+            let solution = solve(&input, &config);
+            assert_valid_solution(&input, &solution, &format!("{:?}, width {}", order, beam_width));
+        }
+    }
+}
+
+#[test]
+fn stalled_stages_give_up_sooner_than_the_global_search() {
+    let input = read("in4.txt");
+    // every search stalls at this width; the global one only after the default stall limit
+    let timed = |stages| {
+        let config = SolverConfig { stages, beam_width: 5, ..SolverConfig::default() };
+        let start = Instant::now();
+        let solution = solve(&input, &config);
+        assert_valid_solution(&input, &solution, &format!("{:?}", stages));
+        start.elapsed().as_secs_f64()
+    };
+    let global = timed(None);
+    for order in [StageOrder::Rings, StageOrder::Rows] {
+        let staged = timed(Some(order));
+        assert!(4.0 * staged < global, "{:?}: {:.2}s, the global search {:.2}s", order, staged, global);
+    }
+}
@@ -0,0 +1,16 @@
+mod common;
+
+use common::{assert_valid_solution, read};
+use solver::{solve, SolverConfig};
+
+#[test]
+fn stopped_search_is_finished_without_a_time_limit() {
+    let input = read("in5.txt");
+    // no time limit; a beam this narrow stalls long before it solves the board, and 30 moves
+    // are far too few
+    for (stall_limit, max_depth) in [(Some(20), None), (None, Some(30))] {
+        let config = SolverConfig { beam_width: 1, stall_limit, max_depth, ..SolverConfig::default() };
+        let solution = solve(&input, &config);
+        assert_valid_solution(&input, &solution, &format!("stall limit {:?}, max depth {:?}", stall_limit, max_depth));
+    }
+}
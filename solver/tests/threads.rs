@@ -0,0 +1,12 @@
+use solver::utils::random_moves;
+use solver::{solve, Input, SolverConfig};
+
+#[test]
+fn threads_do_not_change_the_solution() {
+    let input = Input::scrambled(5, &random_moves(200, 5));
+    // the first depths are too narrow for the workers, the later ones use them
+    let solve_with = |threads| solve(&input, &SolverConfig { beam_width: 200, threads, ..SolverConfig::default() });
+    let (single, multi) = (solve_with(1), solve_with(4));
+    assert!(single.solved, "not solved");
+    assert_eq!(single.moves, multi.moves, "the threads changed the solution");
+}
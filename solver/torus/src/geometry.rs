@@ -0,0 +1,234 @@
+use crate::moves::{Move, DIRECTIONS};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+type CellPair = ((usize, usize), (usize, usize));
+
+/// Cell layout of the hexagonal torus of size `n`, with every wrapped neighbor precomputed.
+///
+/// Cells are `(i, j)` in the `(2n-1) x (2n-1)` board, valid iff `|i - j| < n`. Each valid cell
+/// also has a compact id, its rank in row-major order.
+pub struct Geometry {
+    n: usize,
+    size: usize,
+    cells: Vec<(usize, usize)>,
+    cell_ids: Vec<usize>,
+    distances: Vec<u8>,
+    max_distance: u32,
+    offsets_by_distance: Vec<(i32, i32)>,
+    neighbors: Vec<[(usize, usize); 6]>,
+    rotated: Vec<[CellPair; 12]>,
+}
+
+impl Geometry {
+    pub fn new(n: usize) -> Self {
+        let size = 2 * n - 1;
+        let mut geometry = Geometry {
+            n,
+            size,
+            cells: vec![],
+            cell_ids: vec![usize::MAX; size * size],
+            distances: vec![],
+            max_distance: 0,
+            offsets_by_distance: vec![],
+            neighbors: vec![[(0, 0); 6]; size * size],
+            rotated: vec![[((0, 0), (0, 0)); 12]; size * size],
+        };
+        for i in 0..size {
+            for j in 0..size {
+                if !geometry.is_valid_coordinates(i as i32, j as i32) {
+                    continue;
+                }
+                let mut neighbors = [(0, 0); 6];
+                for (d, &(di, dj)) in DIRECTIONS.iter().enumerate() {
+                    neighbors[d] = geometry.wrap_coordinates(i as i32 + di, j as i32 + dj);
+                }
+                let mut rotated = [((0, 0), (0, 0)); 12];
+                for m in Move::ALL {
+                    let ((b_di, b_dj), (c_di, c_dj)) = m.neighbors();
+                    rotated[m.index()] = (geometry.wrap_coordinates(i as i32 + b_di, j as i32 + b_dj), geometry.wrap_coordinates(i as i32 + c_di, j as i32 + c_dj));
+                }
+                geometry.neighbors[i * size + j] = neighbors;
+                geometry.rotated[i * size + j] = rotated;
+                geometry.cell_ids[i * size + j] = geometry.cells.len();
+                geometry.cells.push((i, j));
+            }
+        }
+
+        let offsets = geometry.periods();
+        let mut distances = Vec::with_capacity(geometry.cells.len() * geometry.cells.len());
+        for &(i, j) in geometry.cells.iter() {
+            for &(i2, j2) in geometry.cells.iter() {
+                let distance = offsets.iter().map(|&(di, dj)| lattice_distance(i as i32 + di, j as i32 + dj, i2 as i32, j2 as i32)).min().unwrap();
+                distances.push(distance as u8);
+            }
+        }
+        geometry.max_distance = distances.iter().copied().max().unwrap_or(0) as u32;
+        geometry.distances = distances;
+
+        let center = (n - 1, n - 1);
+        let mut by_distance = geometry.cells.clone();
+        by_distance.sort_by_key(|&cell| geometry.distance(center, cell));
+        geometry.offsets_by_distance = by_distance.iter().map(|&(i, j)| (i as i32 - center.0 as i32, j as i32 - center.1 as i32)).collect();
+        geometry
+    }
+
+    /// Translations that map the board onto its wrapped copies, `(0, 0)` first.
+    fn periods(&self) -> [(i32, i32); 7] {
+        [
+            (0, 0),
+            (-(self.n as i32) + 1, self.n as i32),
+            (self.n as i32, 2 * self.n as i32 - 1),
+            (2 * self.n as i32 - 1, self.n as i32 - 1),
+            (self.n as i32 - 1, -(self.n as i32)),
+            (-(self.n as i32), -2 * self.n as i32 + 1),
+            (-2 * self.n as i32 + 1, -(self.n as i32) + 1),
+        ]
+    }
+
+    /// Shared tables for `n`, built on first use.
+    pub fn get(n: usize) -> Arc<Geometry> {
+        static CACHE: OnceLock<Mutex<HashMap<usize, Arc<Geometry>>>> = OnceLock::new();
+        let mut cache = CACHE.get_or_init(Default::default).lock().unwrap();
+        cache.entry(n).or_insert_with(|| Arc::new(Geometry::new(n))).clone()
+    }
+
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    pub fn is_valid_coordinates(&self, i: i32, j: i32) -> bool {
+        0 <= i && i < 2 * self.n as i32 - 1 && 0 <= j && j < 2 * self.n as i32 - 1 && i < j + self.n as i32 && j < i + self.n as i32
+    }
+
+    /// Brings any `(i, j)` within one period of the board back onto it.
+    pub fn wrap_coordinates(&self, i: i32, j: i32) -> (usize, usize) {
+        let mut wrapped_i = i;
+        let mut wrapped_j = j;
+        let offsets = self.periods();
+        for &(di, dj) in offsets.iter() {
+            if self.is_valid_coordinates(i + di, j + dj) {
+                wrapped_i = i + di;
+                wrapped_j = j + dj;
+                break;
+            }
+        }
+        (wrapped_i as usize, wrapped_j as usize)
+    }
+
+    /// `(i, j)` shifted by `(di, dj)` on the torus; the move structure is invariant under it.
+    pub fn translate(&self, (i, j): (usize, usize), (di, dj): (i32, i32)) -> (usize, usize) {
+        let (wrapped_i, wrapped_j) = self.wrap_coordinates(i as i32 + di, j as i32 + dj);
+        debug_assert!(self.is_valid_coordinates(wrapped_i as i32, wrapped_j as i32));
+        (wrapped_i, wrapped_j)
+    }
+
+    /// Shortest offset from `a` to `b` on the torus: `translate(a, offset(a, b)) == b`.
+    pub fn offset(&self, a: (usize, usize), b: (usize, usize)) -> (i32, i32) {
+        let (di, dj) = (b.0 as i32 - a.0 as i32, b.1 as i32 - a.1 as i32);
+        self.periods()
+            .iter()
+            .map(|&(pi, pj)| (di + pi, dj + pj))
+            .min_by_key(|&(di, dj)| lattice_distance(0, 0, di, dj))
+            .unwrap()
+    }
+
+    /// The six wrapped neighbors of `(i, j)`, in the order of `DIRECTIONS`.
+    pub fn neighbors(&self, (i, j): (usize, usize)) -> &[(usize, usize); 6] {
+        &self.neighbors[i * self.size + j]
+    }
+
+    /// The two cells rotated by `m` when the blank is at `(i, j)`, see `Move::neighbors`.
+    pub fn rotated_cells(&self, (i, j): (usize, usize), m: Move) -> CellPair {
+        self.rotated[i * self.size + j][m.index()]
+    }
+
+    /// All valid cells, indexed by their id.
+    pub fn cells(&self) -> &[(usize, usize)] {
+        &self.cells
+    }
+
+    pub fn cell_id(&self, (i, j): (usize, usize)) -> usize {
+        self.cell_ids[i * self.size + j]
+    }
+
+    /// Number of moves of a tile from `a` to `b` on the torus, ignoring every other tile.
+    pub fn distance(&self, a: (usize, usize), b: (usize, usize)) -> u32 {
+        self.distances[self.cell_id(a) * self.cells.len() + self.cell_id(b)] as u32
+    }
+
+    /// Offset of every cell from the center, nearest first; `translate` by them visits the board
+    /// in order of distance from any cell.
+    pub fn offsets_by_distance(&self) -> &[(i32, i32)] {
+        &self.offsets_by_distance
+    }
+
+    pub fn max_distance(&self) -> u32 {
+        self.max_distance
+    }
+
+    /// `d^exponent` rounded, for every distance `d` up to `max_distance`.
+    pub fn weighted_distances(&self, exponent: f64) -> Vec<u32> {
+        (0..=self.max_distance).map(|d| (d as f64).powf(exponent).round() as u32).collect()
+    }
+}
+
+/// Hex distance on the unwrapped lattice, where `(1, 1)` is a single step.
+fn lattice_distance(i: i32, j: i32, i2: i32, j2: i32) -> u32 {
+    if i < i2 && j < j2 {
+        (i2 - i).max(j2 - j) as u32
+    } else if i > i2 && j > j2 {
+        (i - i2).max(j - j2) as u32
+    } else {
+        (i - i2).unsigned_abs() + (j - j2).unsigned_abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `wrap_coordinates` as the solver first wrote it, before the tables.
+    fn wrap(n: usize, i: i32, j: i32) -> (usize, usize) {
+        let n = n as i32;
+        let valid = |i: i32, j: i32| 0 <= i && i < 2 * n - 1 && 0 <= j && j < 2 * n - 1 && i < j + n && j < i + n;
+        let offsets = [(0, 0), (-n + 1, n), (n, 2 * n - 1), (2 * n - 1, n - 1), (n - 1, -n), (-n, -2 * n + 1), (-2 * n + 1, -n + 1)];
+        let (di, dj) = offsets.into_iter().find(|&(di, dj)| valid(i + di, j + dj)).unwrap_or((0, 0));
+        ((i + di) as usize, (j + dj) as usize)
+    }
+
+    type Offset = (i32, i32);
+
+    /// The cells each move rotated, as offsets from the blank, in the solver's first `apply`.
+    const ROTATED: [(char, Offset, Offset); 12] = [
+        ('1', (-1, 0), (0, 1)),
+        ('2', (0, 1), (1, 1)),
+        ('3', (1, 1), (1, 0)),
+        ('4', (1, 0), (0, -1)),
+        ('5', (0, -1), (-1, -1)),
+        ('6', (-1, -1), (-1, 0)),
+        ('A', (0, 1), (-1, 0)),
+        ('B', (1, 1), (0, 1)),
+        ('C', (1, 0), (1, 1)),
+        ('D', (0, -1), (1, 0)),
+        ('E', (-1, -1), (0, -1)),
+        ('F', (-1, 0), (-1, -1)),
+    ];
+
+    #[test]
+    fn tables_match_the_wrapping_formulas() {
+        for n in [2, 3, 5, 8] {
+            let geometry = Geometry::new(n);
+            assert_eq!(geometry.cells().len(), 3 * n * n - 3 * n + 1, "n = {}", n);
+            for &(i, j) in geometry.cells() {
+                let at = |(di, dj): (i32, i32)| wrap(n, i as i32 + di, j as i32 + dj);
+                let neighbors = DIRECTIONS.map(at);
+                assert_eq!(geometry.neighbors((i, j)), &neighbors, "n = {}, ({}, {})", n, i, j);
+                for (c, b, d) in ROTATED {
+                    let m = Move::try_from(c).unwrap();
+                    assert_eq!(geometry.rotated_cells((i, j), m), (at(b), at(d)), "n = {}, ({}, {}), {}", n, i, j, c);
+                }
+            }
+        }
+    }
+}
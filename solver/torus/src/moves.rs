@@ -0,0 +1,166 @@
+use std::fmt;
+
+/// The six neighbors of a cell, clockwise starting from the upper one.
+pub const DIRECTIONS: [(i32, i32); 6] = [(-1, 0), (0, 1), (1, 1), (1, 0), (0, -1), (-1, -1)];
+
+/// A rotation of the blank with two of its neighbors. `Cw1`..`Cw6` are written `1`..`6` and
+/// `CcwA`..`CcwF` are written `A`..`F` in the contest format.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Move {
+    Cw1,
+    Cw2,
+    Cw3,
+    Cw4,
+    Cw5,
+    Cw6,
+    CcwA,
+    CcwB,
+    CcwC,
+    CcwD,
+    CcwE,
+    CcwF,
+}
+
+#[derive(Debug)]
+pub struct ParseMoveError(pub char);
+
+impl fmt::Display for ParseMoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid move {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseMoveError {}
+
+impl Move {
+    pub const ALL: [Move; 12] = [
+        Move::Cw1,
+        Move::Cw2,
+        Move::Cw3,
+        Move::Cw4,
+        Move::Cw5,
+        Move::Cw6,
+        Move::CcwA,
+        Move::CcwB,
+        Move::CcwC,
+        Move::CcwD,
+        Move::CcwE,
+        Move::CcwF,
+    ];
+
+    /// 0..6 for clockwise moves, 6..12 for anticlockwise ones.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn from_index(idx: usize) -> Move {
+        Move::ALL[idx]
+    }
+
+    pub fn is_clockwise(self) -> bool {
+        self.index() < 6
+    }
+
+    /// The move that brings the blank and both neighbors back.
+    pub fn inverse(self) -> Move {
+        let k = self.index() % 6;
+        if self.is_clockwise() {
+            Move::from_index(6 + (k + 2) % 6)
+        } else {
+            Move::from_index((k + 4) % 6)
+        }
+    }
+
+    /// Offsets of the two neighbors rotated with the blank. The blank moves to the first one,
+    /// the first one moves to the second one and the second one moves to the blank.
+    pub fn neighbors(self) -> ((i32, i32), (i32, i32)) {
+        let k = self.index() % 6;
+        if self.is_clockwise() {
+            (DIRECTIONS[k], DIRECTIONS[(k + 1) % 6])
+        } else {
+            (DIRECTIONS[(k + 1) % 6], DIRECTIONS[k])
+        }
+    }
+
+    pub fn to_char(self) -> char {
+        b"123456ABCDEF"[self.index()] as char
+    }
+}
+
+impl TryFrom<char> for Move {
+    type Error = ParseMoveError;
+
+    fn try_from(c: char) -> Result<Self, Self::Error> {
+        match c {
+            '1'..='6' => Ok(Move::from_index(c as usize - '1' as usize)),
+            'A'..='F' => Ok(Move::from_index(6 + c as usize - 'A' as usize)),
+            _ => Err(ParseMoveError(c)),
+        }
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+/// Parses a solution in the contest format, ignoring whitespace.
+pub fn parse_moves(s: &str) -> Result<Vec<Move>, ParseMoveError> {
+    s.chars().filter(|c| !c.is_whitespace()).map(Move::try_from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every move with its contest letter and its inverse.
+    const MOVES: [(Move, char, Move); 12] = [
+        (Move::Cw1, '1', Move::CcwC),
+        (Move::Cw2, '2', Move::CcwD),
+        (Move::Cw3, '3', Move::CcwE),
+        (Move::Cw4, '4', Move::CcwF),
+        (Move::Cw5, '5', Move::CcwA),
+        (Move::Cw6, '6', Move::CcwB),
+        (Move::CcwA, 'A', Move::Cw5),
+        (Move::CcwB, 'B', Move::Cw6),
+        (Move::CcwC, 'C', Move::Cw1),
+        (Move::CcwD, 'D', Move::Cw2),
+        (Move::CcwE, 'E', Move::Cw3),
+        (Move::CcwF, 'F', Move::Cw4),
+    ];
+
+    #[test]
+    fn inverse_undoes_the_rotation() {
+        for (m, _, inverse) in MOVES {
+            assert_eq!(m.inverse(), inverse, "{:?}", m);
+            assert_eq!(m.inverse().inverse(), m, "{:?}", m);
+            assert_ne!(m.is_clockwise(), m.inverse().is_clockwise(), "{:?}", m);
+            // from the cell the blank moved to, the inverse sends it back and brings the tile
+            // that went from the first neighbor to the second one back
+            let (b, c) = m.neighbors();
+            assert_eq!(inverse.neighbors(), ((-b.0, -b.1), (c.0 - b.0, c.1 - b.1)), "{:?}", m);
+        }
+    }
+
+    #[test]
+    fn letters_round_trip() {
+        for (m, c, _) in MOVES {
+            assert_eq!(Move::try_from(c).unwrap(), m, "{:?}", c);
+            assert_eq!(m.to_string(), c.to_string(), "{:?}", m);
+            assert_eq!(Move::from_index(m.index()), m, "{:?}", m);
+        }
+        for c in ['0', '7', 'G', 'a', 'f', ' ', '-'] {
+            assert!(matches!(Move::try_from(c), Err(ParseMoveError(e)) if e == c), "{:?} accepted", c);
+        }
+    }
+
+    #[test]
+    fn parse_moves_reports_the_first_invalid_letter() {
+        assert_eq!(parse_moves(" 1A\n6 F\t").unwrap(), [Move::Cw1, Move::CcwA, Move::Cw6, Move::CcwF]);
+        assert!(parse_moves("").unwrap().is_empty());
+        let error = parse_moves("12x3y").unwrap_err();
+        assert_eq!(error.0, 'x');
+        assert_eq!(error.to_string(), "invalid move 'x'");
+    }
+}